@@ -0,0 +1,29 @@
+#!/bin/sh
+# Wall time and peak memory (maximum resident set size) of `eggrtl stats` on one netlist,
+# with the importer of a baseline revision and with the current one:
+#
+#     benches/compare_import.sh systolic.json [top] [baseline]
+#
+# The baseline is any git revision, `$BASELINE` if not given and `HEAD~1` by default, which
+# is the importer from before the typed Yosys structs when run at the commit adding them.
+# That importer read the whole file into a `serde_json::Value` tree before importing it.
+# Both revisions are built in release mode; the baseline in a temporary git worktree.
+# Needs GNU time as /usr/bin/time.
+set -eu
+
+netlist=$(realpath "$1")
+top=${2:-top}
+baseline=${3:-${BASELINE:-HEAD~1}}
+repo=$(git rev-parse --show-toplevel)
+work=$(mktemp -d)
+trap 'git -C "$repo" worktree remove --force "$work/before" 2>/dev/null || true; rm -rf "$work"' EXIT
+
+git -C "$repo" worktree add --detach --quiet "$work/before" "$baseline"
+cargo build --release --quiet --manifest-path "$work/before/Cargo.toml"
+cargo build --release --quiet --manifest-path "$repo/Cargo.toml"
+
+measure() {
+    /usr/bin/time -f "$1  %e s  %M KiB max RSS" "$2" stats --top "$top" "$netlist" > /dev/null
+}
+measure before "$work/before/target/release/eggrtl"
+measure now "$repo/target/release/eggrtl"
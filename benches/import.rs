@@ -0,0 +1,91 @@
+//! Import time and peak heap usage of the two ways to read a Yosys JSON netlist:
+//!
+//!     cargo bench --bench import -- systolic.json [top]
+//!
+//! `typed` parses the file into the `yosys` structs; `streaming` imports cells while parsing.
+//! Each runs on its own, so the peak is that of one import. `compare_import.sh` measures the
+//! importer from before the typed structs, which parsed into a `serde_json::Value` tree.
+
+use eggrtl::core::NetlistDatabase;
+use eggrtl::hierarchy::Hierarchy;
+use eggrtl::yosys::Netlist;
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Instant
+};
+
+
+/// The system allocator, keeping track of the most bytes allocated at once.
+struct PeakAlloc;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+fn grow(size: usize) {
+    let now = CURRENT.fetch_add(size, Ordering::Relaxed) + size;
+    PEAK.fetch_max(now, Ordering::Relaxed);
+}
+
+unsafe impl GlobalAlloc for PeakAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc(layout) };
+        if !ptr.is_null() {
+            grow(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) };
+        CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = unsafe { System.realloc(ptr, layout, new_size) };
+        if !new_ptr.is_null() {
+            CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+            grow(new_size);
+        }
+        new_ptr
+    }
+}
+
+#[global_allocator]
+static ALLOC: PeakAlloc = PeakAlloc;
+
+fn measure(name: &str, import: impl FnOnce() -> NetlistDatabase) {
+    let base = CURRENT.load(Ordering::Relaxed);
+    PEAK.store(base, Ordering::Relaxed);
+    let start = Instant::now();
+    let netlist = import();
+    let elapsed = start.elapsed();
+    let peak = PEAK.load(Ordering::Relaxed) - base;
+    let cells = netlist.stats().cells();
+    println!(
+        "{:<10} {:>10.1} ms {:>10.1} MiB peak {:>10} cells {:>12.0} cells/s",
+        name, elapsed.as_secs_f64() * 1000.0, peak as f64 / (1024.0 * 1024.0), cells, cells as f64 / elapsed.as_secs_f64()
+    );
+}
+
+fn main() {
+    // cargo passes `--bench` along with our arguments
+    let args: Vec<String> = std::env::args().skip(1).filter(|a| !a.starts_with("--")).collect();
+    let Some(path) = args.first() else {
+        eprintln!("usage: cargo bench --bench import -- <netlist.json> [top]");
+        return;
+    };
+    let top = args.get(1).map(String::as_str).unwrap_or("top");
+
+    measure("typed", || {
+        let netlist = Netlist::load(path).unwrap_or_else(|err| panic!("{}", err));
+        let mut db = NetlistDatabase::default();
+        db.build_mod(top, &netlist.modules, &[], Hierarchy::Flatten).unwrap_or_else(|err| panic!("{}", err));
+        db
+    });
+    measure("streaming", || {
+        let mut db = NetlistDatabase::default();
+        db.build_from_json(path, top, &[], Hierarchy::Flatten).unwrap_or_else(|err| panic!("{}", err));
+        db
+    });
+}
@@ -0,0 +1,110 @@
+use core_relations::{TableId, Value};
+use std::collections::HashMap;
+use crate::core::{NetlistDatabase, VecContainer};
+use crate::error::{Location, NetlistError};
+use crate::yosys::JsonBit;
+
+
+/// A column of a row staged during import. Wirevecs stay plain wire lists until the batch is
+/// written, so their containers are registered together instead of one execution state each.
+pub(crate) enum Staged {
+    Val(Value),
+    Vec(Vec<Value>),        // a wirevec
+    Vecs(Vec<Vec<Value>>)   // a vec of wirevecs, like the ports of opaque cells
+}
+
+impl NetlistDatabase {
+    /// Rows staged before they are written to their tables.
+    pub(crate) const BATCH_ROWS: usize = 1 << 14;
+
+    pub(crate) fn stage_wirevec(&mut self, bits: &[JsonBit], loc: &Location) -> Result<Staged, NetlistError> {
+        Ok(Staged::Vec(self.create_or_lookup_wires(bits, loc)?))
+    }
+
+    pub(crate) fn stage_row(&mut self, table: TableId, row: Vec<Staged>) {
+        self.staged.push((table, row));
+        if self.staged.len() >= Self::BATCH_ROWS {
+            self.flush_staged();
+        }
+    }
+
+    /// Registers the wirevecs of every staged row in one execution state, then writes the rows
+    /// through one buffer per table. They show up in the tables after the next `merge_all`.
+    pub(crate) fn flush_staged(&mut self) {
+        if self.staged.is_empty() {
+            return;
+        }
+        let staged = std::mem::take(&mut self.staged);
+        let rows: Vec<(TableId, Vec<Value>)> = self.db.with_execution_state(|state| {
+            let mut rows = Vec::with_capacity(staged.len());
+            for (table, row) in staged {
+                let mut vals = Vec::with_capacity(row.len());
+                for col in row {
+                    let val = match col {
+                        Staged::Val(val) => val,
+                        Staged::Vec(wires) => state.container_values().register_val(VecContainer(wires), state),
+                        Staged::Vecs(vecs) => {
+                            let mut ids = Vec::with_capacity(vecs.len());
+                            for wires in vecs {
+                                ids.push(state.container_values().register_val(VecContainer(wires), state));
+                            }
+                            state.container_values().register_val(VecContainer(ids), state)
+                        }
+                    };
+                    vals.push(val);
+                }
+                rows.push((table, vals));
+            }
+            rows
+        });
+
+        let mut bufs = HashMap::new();  // written to the tables when dropped
+        for (table, row) in rows.iter() {
+            bufs.entry(*table)
+                .or_insert_with(|| self.db.get_table(*table).new_buffer())
+                .stage_insert(row);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hierarchy::Hierarchy;
+    use crate::yosys::Netlist;
+    use std::time::Instant;
+
+    fn sorted_cells(db: &NetlistDatabase) -> Vec<String> {
+        let json = db.export_json("top");
+        let mut cells: Vec<String> = json["modules"]["top"]["cells"].as_object().unwrap().values().map(|c| c.to_string()).collect();
+        cells.sort();
+        cells
+    }
+
+    #[test]
+    fn batched_rows_fill_the_same_tables_as_rows_written_one_by_one() {
+        let netlist = Netlist::load(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/mac.json")).unwrap();
+        let mut batched = NetlistDatabase::default();
+        batched.build_mod("top", &netlist.modules, &[], Hierarchy::Flatten).unwrap();
+
+        // the steps of `build_mod`, writing each staged row before the next cell
+        let module = &netlist.modules["top"];
+        let mut single = NetlistDatabase::default();
+        single.build_ports("top", &module.ports, &[]).unwrap();
+        for (name, netname) in module.netnames.iter() {
+            single.build_netname("top", name, netname).unwrap();
+        }
+        let mut others = Vec::new();
+        for (name, cell) in module.cells.iter() {
+            if let Some(cell) = single.build_cell(cell, &Location::cell("top", name, cell.src())).unwrap() {
+                others.push((name.as_str(), cell));
+            }
+            single.flush_staged();
+        }
+        single.build_instances("top", &others, &netlist.modules, Hierarchy::Flatten).unwrap();
+        single.finish_mod("top", module.cells.len(), Instant::now());
+
+        assert_eq!(batched.stats(), single.stats());
+        assert_eq!(sorted_cells(&batched), sorted_cells(&single));
+    }
+}
@@ -1,14 +1,21 @@
 use core_relations::{CounterId, Database, DisplacedTable, TableId, Value, Rebuilder, ContainerValue, SortedWritesTable, ColumnId};
 use numeric_id::NumericId;
 use serde_json::json;
-use core::panic;
 use std::{
     time::Instant,
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     fmt::Debug,
     iter
 };
 use bimap::BiHashMap;
+use log::{debug, info, trace};
+use crate::batch::Staged;
+use crate::error::{Location, NetlistError};
+use crate::hierarchy::{Flattened, Hierarchy};
+use crate::param::ParamBits;
+use crate::progress::{Progress, ProgressSink};
+use crate::rewrites::{ExternalFuncs, const_bits_value};
+use crate::yosys::{self, JsonBit};
 
 
 pub struct NetlistDatabase {
@@ -20,15 +27,97 @@ pub struct NetlistDatabase {
     pub(crate) aby_cells: TableId,
     pub(crate) absy_cells: TableId,
     pub(crate) dffs: TableId,
+    pub(crate) opaque_cells: TableId,
+    pub(crate) const_words: TableId,
+    pub(crate) const_words_from: usize,     // wirevecs with smaller ids are in `const_words` if constant
     pub(crate) types: BiHashMap<String, Value>,  // we use external containers to record types and wires
     pub(crate) wires: BiHashMap<i64, Value>,
-    pub(crate) clk: i64,
+    pub(crate) netnames: Vec<NetName>,
+    pub(crate) wire_names: HashMap<i64, Vec<(usize, usize)>>,  // wire id -> (index in netnames, bit)
+    pub(crate) opaque_sigs: BiHashMap<OpaqueSig, Value>,
+    pub(crate) reg_kinds: BiHashMap<RegKind, Value>,
+    pub(crate) empty_vec: Value,
+    pub(crate) funcs: ExternalFuncs,
+    pub(crate) clks: Vec<i64>,  // wire ids of the clock domains, declared or inferred from register clock ports
+    pub(crate) clks_declared: bool,
     pub(crate) from_inputs: HashMap<(String, i64), i64>,   // (port name, index) -> wire id
-    pub(crate) as_outputs: HashMap<(String, i64), i64>
+    pub(crate) as_outputs: HashMap<(String, i64), i64>,
+    pub(crate) progress: Option<ProgressSink>,
+    pub(crate) staged: Vec<(TableId, Vec<Staged>)>   // rows of the import not written yet
+}
+
+/// Tries to import a cell into the tables, handing it back if the cell type is not its own.
+type CellBuilder = for<'a> fn(&mut NetlistDatabase, &'a yosys::Cell, Value, &Location) -> Result<Option<&'a yosys::Cell>, NetlistError>;
+
+/// A snapshot of rows from the four cell tables, in their table layouts (timestamps included).
+#[derive(Clone, Debug, Default)]
+pub struct CellRows {
+    pub ay: Vec<Vec<Value>>,
+    pub aby: Vec<Vec<Value>>,
+    pub absy: Vec<Vec<Value>>,
+    pub dffs: Vec<Vec<Value>>,
+    pub opaque: Vec<Vec<Value>>
+}
+
+/// Sizes of a netlist database, as reported by `eggrtl stats`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NetlistStats {
+    pub ay_cells: usize,
+    pub aby_cells: usize,
+    pub absy_cells: usize,
+    pub dffs: usize,
+    pub opaque_cells: usize,
+    pub wires: usize,
+    pub wirevecs: usize,
+    pub unions: usize,
+    pub const_words: usize,
+    pub clocks: usize,
+    pub netnames: usize
+}
+
+impl NetlistStats {
+    pub fn cells(&self) -> usize {
+        self.ay_cells + self.aby_cells + self.absy_cells + self.dffs + self.opaque_cells
+    }
+}
+
+/// The flavor of a register row: its RTLIL type and the values of its `*_POLARITY` parameters.
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub(crate) struct RegKind {
+    pub(crate) ty: String,
+    pub(crate) polarities: Vec<(String, bool)>  // sorted by parameter name
+}
+
+impl RegKind {
+    pub(crate) fn new(ty: &str, polarities: &[(&str, bool)]) -> Self {
+        let mut polarities: Vec<(String, bool)> = polarities.iter().map(|(n, p)| (n.to_string(), *p)).collect();
+        polarities.sort();
+        Self { ty: ty.to_string(), polarities }
+    }
+}
+
+/// A named signal from the `netnames` section of the input. Its bits are imported wire ids, so
+/// after unions the name follows the canonical wire of each bit's class.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct NetName {
+    pub(crate) name: String,
+    pub(crate) hide_name: bool,
+    pub(crate) src: Option<String>,
+    pub(crate) bits: Vec<i64>   // LSB first
+}
+
+/// Everything about an unsupported cell except its connections: the opaque cell table
+/// refers to it by a single value, like cell types in `types`.
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub(crate) struct OpaqueSig {
+    pub(crate) ty: String,
+    pub(crate) params: String,  // serialized JSON object of the Yosys parameters
+    pub(crate) inputs: Vec<String>,
+    pub(crate) outputs: Vec<String>
 }
 
 #[derive(Clone, Debug, Hash, Eq, PartialEq)]
-struct VecContainer(Vec<Value>);
+pub(crate) struct VecContainer(pub(crate) Vec<Value>);
 impl ContainerValue for VecContainer {
     fn rebuild_contents(&mut self, rebuilder: &dyn Rebuilder) -> bool {
         rebuilder.rebuild_slice(&mut self.0)
@@ -40,46 +129,131 @@ impl ContainerValue for VecContainer {
 }
 
 impl NetlistDatabase {
-    pub(crate) const AY_TYPES: &[&str] = &["$not", "$logic_not"];
+    pub(crate) const AY_TYPES: &[&str] = &[
+        "$nots", "$notu", "$negs", "$negu", "$poss", "$posu", "$logic_not",
+        "$reduce_and", "$reduce_or", "$reduce_xor", "$reduce_xnor", "$reduce_bool"
+    ];
     pub(crate) const ABY_TYPES: &[&str] = &[
-        "$and", "$or", "$xor", "$nand", "$nor", "$xnor",
-        "$eq", "$ge", "$le", "$gt", "$lt", "$logic_and", "$logic_or",
-        "$adds", "$addu", "$subs", "$subu", "$muls", "$mulu", "$divs", "$divu", "$mod"
+        "$ands", "$andu", "$ors", "$oru", "$xors", "$xoru", "$nands", "$nandu", "$nors", "$noru", "$xnors", "$xnoru",
+        "$eqs", "$equ", "$ges", "$geu", "$les", "$leu", "$gts", "$gtu", "$lts", "$ltu", "$logic_and", "$logic_or",
+        "$adds", "$addu", "$subs", "$subu", "$muls", "$mulu", "$divs", "$divu", "$mods", "$modu",
+        "$shls", "$shlu", "$shrs", "$shru", "$sshls", "$sshlu", "$sshrs", "$sshru",
+        "$shifts", "$shiftu", "$shiftxs", "$shiftxu"
     ];
     pub(crate) const ABSY_TYPES: &[&str] = &["$mux"];
 
-    pub(crate) const RTLIL_AY_TYPES: &[&str] = &["$not", "$logic_not"];
+    pub(crate) const RTLIL_AY_TYPES: &[&str] = &[
+        "$not", "$neg", "$pos", "$logic_not",
+        "$reduce_and", "$reduce_or", "$reduce_xor", "$reduce_xnor", "$reduce_bool"
+    ];
+    pub(crate) const RTLIL_AY_SIGNED_TYPES: &[&str] = &["$not", "$neg", "$pos"];  // A is extended to the width of Y
     pub(crate) const RTLIL_ABY_ARITH_TYPES: &[&str] = &["$add", "$sub", "$mul", "$div", "$mod"];
     pub(crate) const RTLIL_ABY_LOGIC_TYPES: &[&str] = &[
         "$and", "$or", "$xor", "$nand", "$nor", "$xnor",
-        "$eq", "$ge", "$le", "$gt", "$lt", "$logic_and", "$logic_or"
+        "$eq", "$ge", "$le", "$gt", "$lt"
     ];
+    pub(crate) const RTLIL_ABY_BOOL_TYPES: &[&str] = &["$logic_and", "$logic_or"];  // only look at whether bits are set
+    pub(crate) const RTLIL_ABY_SHIFT_TYPES: &[&str] = &["$shl", "$shr", "$sshl", "$sshr", "$shift", "$shiftx"];
     pub(crate) const RTLIL_ABSY_TYPES: &[&str] = &["$mux"];
+    // (type, clk port, en port, rst port, rst_val parameter or port), "" if the register has none
+    // set/reset flops keep SET in the rst column and CLR in the rst_val column, async-load flops keep ALOAD and AD
+    pub(crate) const RTLIL_REG_TYPES: &[(&str, &str, &str, &str, &str)] = &[
+        ("$dff", "CLK", "", "", ""),
+        ("$dffe", "CLK", "EN", "", ""),
+        ("$adff", "CLK", "", "ARST", "ARST_VALUE"),
+        ("$sdff", "CLK", "", "SRST", "SRST_VALUE"),
+        ("$adffe", "CLK", "EN", "ARST", "ARST_VALUE"),
+        ("$sdffe", "CLK", "EN", "SRST", "SRST_VALUE"),
+        ("$sdffce", "CLK", "EN", "SRST", "SRST_VALUE"),
+        ("$aldff", "CLK", "", "ALOAD", "AD"),
+        ("$aldffe", "CLK", "EN", "ALOAD", "AD"),
+        ("$dffsr", "CLK", "", "SET", "CLR"),
+        ("$dffsre", "CLK", "EN", "SET", "CLR"),
+        ("$dlatch", "", "EN", "", ""),
+        ("$adlatch", "", "EN", "ARST", "ARST_VALUE"),
+        ("$dlatchsr", "", "EN", "SET", "CLR")
+    ];
+
+    // wire ids of constant bits, Yosys numbers nets from 2 so 0 and 1 never clash. Constants are
+    // not a column of their own: a constant bit is the wire of one of these ids, and a constant
+    // word a wirevec of such wires, its value recorded in `const_words` for rules to match
+    pub(crate) const CONST_BITS: &[(char, i64)] = &[('x', -1), ('z', -2), ('0', 0), ('1', 1)];
+
+    // output ports of opaque cells that come without `port_directions`
+    pub(crate) const OPAQUE_OUTPUT_PORTS: &[&str] = &["Y", "Q"];
+
+    const CELL_BUILDERS: &[CellBuilder] = &[
+        Self::build_ay_cell,
+        Self::build_aby_arith_cell,
+        Self::build_aby_logic_cell,
+        Self::build_aby_shift_cell,
+        Self::build_absy_cell,
+        Self::build_reg_cell
+    ];
 
     // auxiliary functions
-    fn bit_to_i64(bit: &serde_json::Value) -> i64 {
+    fn bit_to_i64(bit: JsonBit, loc: &Location) -> Result<i64, NetlistError> {
         match bit {
-            serde_json::Value::Number(num) => num.as_i64().unwrap(),
-            serde_json::Value::String(s) => {
-                match s.as_str() {
-                    "x" => -1,
-                    "0" => 0,
-                    "1" => 1,
-                    _ => panic!("Invalid bit value: {}", s),
-                }
+            JsonBit::Net(id) => Ok(id),
+            JsonBit::Const(c) => {
+                Self::CONST_BITS.iter()
+                    .find(|(b, _)| *b == c)
+                    .map(|(_, wire)| *wire)
+                    .ok_or_else(|| NetlistError::BadBit { loc: loc.clone(), bit: bit.to_json() })
             }
-            _ => panic!("Invalid bit value: {:?}", bit),
         }
     }
 
-    fn param_to_i64(param: &serde_json::Value) -> i64 {
-        match param {
-            serde_json::Value::Number(num) => num.as_i64().unwrap(),
-            serde_json::Value::String(s) => i64::from_str_radix(s, 2).unwrap(),
-            _ => panic!("Invalid parameter value: {:?}", param),
+    fn param_bits(param: &serde_json::Value, name: &str, loc: &Location) -> Result<ParamBits, NetlistError> {
+        ParamBits::from_json(param).ok_or_else(|| NetlistError::BadParam { loc: loc.clone(), param: name.to_string(), value: param.clone() })
+    }
+
+    pub(crate) fn param_to_i64(param: &serde_json::Value, name: &str, loc: &Location) -> Result<i64, NetlistError> {
+        Self::param_bits(param, name, loc)?
+            .to_i64()
+            .ok_or_else(|| NetlistError::BadParam { loc: loc.clone(), param: name.to_string(), value: param.clone() })
+    }
+
+    fn bool_param(params: &serde_json::Map<String, serde_json::Value>, name: &str, loc: &Location) -> Result<bool, NetlistError> {
+        match params.get(name) {
+            Some(d) => Ok(Self::param_to_i64(d, name, loc)? != 0),
+            None => Ok(false)
+        }
+    }
+
+    /// Constant bits of a parameter, LSB first, as the wire ids of constants.
+    fn param_to_bits(param: &serde_json::Value, width: usize, name: &str, loc: &Location) -> Result<Vec<i64>, NetlistError> {
+        let bits = Self::param_bits(param, name, loc)?.resize(width);
+        Ok(bits.bits().iter().map(|b| {
+            Self::CONST_BITS.iter().find(|(c, _)| c == b).map(|(_, wire)| *wire).expect("Unknown constant bit")
+        }).collect())
+    }
+
+    fn port<'a>(conns: &'a BTreeMap<String, Vec<JsonBit>>, port: &str, loc: &Location) -> Result<&'a [JsonBit], NetlistError> {
+        conns.get(port).map(Vec::as_slice).ok_or_else(|| NetlistError::MissingPort { loc: loc.clone(), port: port.to_string() })
+    }
+
+    /// The bits connected to `port`, checked against the width parameter `param` if the cell has one.
+    fn sized_port<'a>(cell: &'a yosys::Cell, port: &str, param: &str, loc: &Location) -> Result<&'a [JsonBit], NetlistError> {
+        let bits = Self::port(&cell.connections, port, loc)?;
+        match cell.parameters.get(param) {
+            Some(value) if Self::param_to_i64(value, param, loc)? != bits.len() as i64 => {
+                Err(NetlistError::BadParam { loc: loc.clone(), param: param.to_string(), value: value.clone() })
+            },
+            _ => Ok(bits)
         }
     }
 
+    fn bits_to_i64(bits: &[JsonBit], loc: &Location) -> Result<Vec<i64>, NetlistError> {
+        bits.iter().map(|b| Self::bit_to_i64(*b, loc)).collect()
+    }
+
+    fn type_value(&self, cell_type: &str, loc: &Location) -> Result<Value, NetlistError> {
+        self.types.get_by_left(cell_type)
+            .copied()
+            .ok_or_else(|| NetlistError::UnknownType { loc: loc.clone(), ty: cell_type.to_string() })
+    }
+
     fn create_wire(&mut self, wire: i64) -> Value {
         let new_val = Value::from_usize(self.db.inc_counter(self.id_counter));
         self.wires.insert(wire, new_val);
@@ -93,29 +267,171 @@ impl NetlistDatabase {
         self.create_wire(wire)
     }
 
-    fn create_or_lookup_wirevec_from_json(&mut self, bits: &serde_json::Value) -> Value {
-        let vec: Vec<Value> = bits.as_array().unwrap()
-            .iter()
-            .map(|b| self.create_or_lookup_wire(Self::bit_to_i64(b)))
+    pub(crate) fn const_bit(&self, c: char) -> Value {
+        let (_, wire) = Self::CONST_BITS.iter().find(|(b, _)| *b == c).expect("Unknown constant bit");
+        *self.wires.get_by_left(wire).unwrap()
+    }
+
+    /// Records the value of every wirevec made of 0 and 1 bits only (up to 63 bits) in `const_words`,
+    /// so rules can match constant operands. Only wirevecs registered since the previous call are
+    /// checked, see `rebuild` for the ones it changes. Returns the number of constants found.
+    pub(crate) fn index_const_words(&mut self) -> usize {
+        let (c0, c1) = (self.const_bit('0'), self.const_bit('1'));
+        let ts = Value::from_usize(self.db.read_counter(self.ts_counter));
+        let from = self.const_words_from;
+        let mut words = Vec::new();
+        self.db.container_values().for_each::<VecContainer>(|bits, vec| {
+            if vec.index() < from {
+                return;
+            }
+            if let Some(word) = const_bits_value(&bits.0, c0, c1).and_then(|w| i64::try_from(w).ok()) {
+                words.push((vec, word));
+            }
+        });
+        self.const_words_from = self.db.read_counter(self.id_counter);
+        let count = words.len();
+        let rows: Vec<[Value; 3]> = words.into_iter()
+            .map(|(vec, word)| [vec, self.db.base_values().get::<i64>(word), ts])
             .collect();
-        self.db.with_execution_state(|state| {
-            state.container_values().register_val(VecContainer(vec), state)
-        })
+        {
+            let mut buf = self.db.get_table(self.const_words).new_buffer();
+            for row in rows.iter() {
+                buf.stage_insert(row);
+            }
+        }   // flush the buffer
+        self.db.merge_all();
+        count
+    }
+
+    pub(crate) fn create_or_lookup_wires(&mut self, bits: &[JsonBit], loc: &Location) -> Result<Vec<Value>, NetlistError> {
+        Ok(Self::bits_to_i64(bits, loc)?
+            .into_iter()
+            .map(|b| self.create_or_lookup_wire(b))
+            .collect())
+    }
+
+    pub(crate) fn create_or_lookup_reg_kind(&mut self, kind: RegKind) -> Value {
+        if let Some(val) = self.reg_kinds.get_by_left(&kind) {
+            return *val;
+        }
+        let val = Value::from_usize(self.db.inc_counter(self.id_counter));
+        self.reg_kinds.insert(kind, val);
+        val
+    }
+
+    fn create_or_lookup_opaque_sig(&mut self, sig: OpaqueSig) -> Value {
+        if let Some(val) = self.opaque_sigs.get_by_left(&sig) {
+            return *val;
+        }
+        let val = Value::from_usize(self.db.inc_counter(self.id_counter));
+        self.opaque_sigs.insert(sig, val);
+        val
     }
 
     pub fn print_tables(&self) {
         self.db.container_values().for_each::<VecContainer>(|vec, expr| {
-            println!("Container {:?}: {:?}", expr, vec);
+            debug!("Container {:?}: {:?}", expr, vec);
         });
 
-        for table_id in &[self.ay_cells, self.aby_cells, self.absy_cells, self.dffs] {
+        for table_id in &[self.ay_cells, self.aby_cells, self.absy_cells, self.dffs, self.opaque_cells] {
             let table = self.db.get_table(*table_id);
             let rows = table.all();
             let rows = table.scan(rows.as_ref());
             for row in rows.iter() {
-                println!("Table {:?}: {:?}", table_id, row);
+                debug!("Table {:?}: {:?}", table_id, row);
+            }
+        }
+    }
+
+    pub(crate) fn table_rows(&self, table_id: TableId) -> Vec<Vec<Value>> {
+        let table = self.db.get_table(table_id);
+        let rows = table.all();
+        let rows = table.scan(rows.as_ref());
+        rows.iter().map(|(_, row)| row.to_vec()).collect()
+    }
+
+    pub(crate) fn wirevecs(&self) -> HashMap<Value, Vec<Value>> {
+        let mut wirevecs = HashMap::new();
+        self.db.container_values().for_each::<VecContainer>(|vec, expr| {
+            wirevecs.insert(expr, vec.0.clone());
+        });
+        wirevecs
+    }
+
+    pub(crate) fn canon_map(&self) -> HashMap<Value, Value> {
+        // replay the union-find in `displaced`, the smallest id of each class being its representative
+        fn find(parent: &mut HashMap<Value, Value>, val: Value) -> Value {
+            let mut root = val;
+            while let Some(&p) = parent.get(&root) {
+                if p == root {
+                    break;
+                }
+                root = p;
+            }
+            let mut cur = val;
+            while cur != root {
+                let next = parent.insert(cur, root).unwrap();
+                cur = next;
+            }
+            root
+        }
+
+        let mut parent = HashMap::new();
+        for row in self.table_rows(self.displaced) {
+            let (a, b) = (find(&mut parent, row[0]), find(&mut parent, row[1]));
+            if a != b {
+                parent.insert(std::cmp::max(a, b), std::cmp::min(a, b));
             }
         }
+        let vals: Vec<Value> = parent.keys().copied().collect();
+        vals.into_iter().map(|v| (v, find(&mut parent, v))).collect()
+    }
+
+    /// The names of every canonical wire, as `name` for single-bit signals and `name[i]` otherwise.
+    pub fn class_names(&self) -> HashMap<Value, Vec<String>> {
+        let canon = self.canon_map();
+        let mut names: HashMap<Value, Vec<String>> = HashMap::new();
+        for (wire, refs) in self.wire_names.iter() {
+            let val = *self.wires.get_by_left(wire).expect("Named wire not found");
+            let root = *canon.get(&val).unwrap_or(&val);
+            for (netname, i) in refs.iter() {
+                let netname = &self.netnames[*netname];
+                names.entry(root).or_default().push(match netname.bits.len() {
+                    1 => netname.name.clone(),
+                    _ => format!("{}[{}]", netname.name, i)
+                });
+            }
+        }
+        for names in names.values_mut() {
+            names.sort();
+        }
+        names
+    }
+
+    pub fn all_rows(&self) -> CellRows {
+        CellRows {
+            ay: self.table_rows(self.ay_cells),
+            aby: self.table_rows(self.aby_cells),
+            absy: self.table_rows(self.absy_cells),
+            dffs: self.table_rows(self.dffs),
+            opaque: self.table_rows(self.opaque_cells)
+        }
+    }
+
+    pub fn stats(&self) -> NetlistStats {
+        NetlistStats {
+            ay_cells: self.table_rows(self.ay_cells).len(),
+            aby_cells: self.table_rows(self.aby_cells).len(),
+            absy_cells: self.table_rows(self.absy_cells).len(),
+            dffs: self.table_rows(self.dffs).len(),
+            opaque_cells: self.table_rows(self.opaque_cells).len(),
+            wires: self.wires.len(),
+            wirevecs: self.wirevecs().len(),
+            unions: self.table_rows(self.displaced).len(),
+            const_words: self.table_rows(self.const_words).len(),
+            clocks: self.clks.len(),
+            netnames: self.netnames.len()
+        }
     }
 
     pub fn dump_tables(&self) -> serde_json::Value {
@@ -126,8 +442,13 @@ impl NetlistDatabase {
                 "wires": vec.0.iter().map(|v| v.rep()).collect::<Vec<_>>()
             }));
         });
+        let mut netnames: Vec<_> = self.class_names().into_iter()
+            .map(|(wire, names)| json!({ "wire": wire.rep(), "names": names }))
+            .collect();
+        netnames.sort_by_key(|n| n["wire"].as_u64());
         json!({
-            "wirevecs": wirevecs
+            "wirevecs": wirevecs,
+            "netnames": netnames
         })
     }
 
@@ -156,9 +477,9 @@ impl NetlistDatabase {
 
         // (type, a, y, t)
         let ay_cells_impl = SortedWritesTable::new(
-            2, 4, Some(ColumnId::new(3)), Vec::new(),
+            2, 4, Some(ColumnId::new(3)), vec![ColumnId::new(1), ColumnId::new(2)],
             Box::new(move |state, expr1, expr2, res| {
-                if expr1 != expr2 {
+                if expr1[..3] != expr2[..3] {
                     let vec1 = &state.container_values().get_val::<VecContainer>(expr1[2]).unwrap().0;
                     let vec2 = &state.container_values().get_val::<VecContainer>(expr2[2]).unwrap().0;
                     assert_eq!(vec1.len(), vec2.len());
@@ -171,7 +492,7 @@ impl NetlistDatabase {
                     true
                 }
                 else {
-                    false
+                    false   // only the timestamp differs, the older row stays
                 }
             })
         );
@@ -179,9 +500,9 @@ impl NetlistDatabase {
 
         // (type, a, b, y, t)
         let aby_cells_impl = SortedWritesTable::new(
-            3, 5, Some(ColumnId::new(4)), Vec::new(),
+            3, 5, Some(ColumnId::new(4)), (1..4).map(ColumnId::new).collect(),
             Box::new(move |state, expr1, expr2, res| {
-                if expr1 != expr2 {
+                if expr1[..4] != expr2[..4] {
                     let vec1 = &state.container_values().get_val::<VecContainer>(expr1[3]).unwrap().0;
                     let vec2 = &state.container_values().get_val::<VecContainer>(expr2[3]).unwrap().0;
                     assert_eq!(vec1.len(), vec2.len());
@@ -195,16 +516,16 @@ impl NetlistDatabase {
                     true
                 }
                 else {
-                    false
+                    false   // only the timestamp differs, the older row stays
                 }
             })
         );
         let aby_cells = db.add_table(aby_cells_impl, iter::once(displaced), iter::once(displaced));
 
         let absy_cells_impl = SortedWritesTable::new(
-            4, 6, Some(ColumnId::new(5)), Vec::new(),
+            4, 6, Some(ColumnId::new(5)), (1..5).map(ColumnId::new).collect(),
             Box::new(move |state, expr1, expr2, res| {
-                if expr1 != expr2 {
+                if expr1[..5] != expr2[..5] {
                     let vec1 = &state.container_values().get_val::<VecContainer>(expr1[4]).unwrap().0;
                     let vec2 = &state.container_values().get_val::<VecContainer>(expr2[4]).unwrap().0;
                     assert_eq!(vec1.len(), vec2.len());
@@ -217,237 +538,459 @@ impl NetlistDatabase {
                     true
                 }
                 else {
-                    false
+                    false   // only the timestamp differs, the older row stays
                 }
             })
         );
         let absy_cells = db.add_table(absy_cells_impl, iter::once(displaced), iter::once(displaced));
 
+        // (kind, clk, en, rst, rst_val, d, q, t), unused controls are the empty wirevec
         let dffs_impl = SortedWritesTable::new(
-            1, 3, Some(ColumnId::new(2)), Vec::new(),
+            6, 8, Some(ColumnId::new(7)), (1..7).map(ColumnId::new).collect(),
             Box::new(move |state, expr1, expr2, res| {
-                if expr1 != expr2 {
-                    let vec1 = &state.container_values().get_val::<VecContainer>(expr1[1]).unwrap().0;
-                    let vec2 = &state.container_values().get_val::<VecContainer>(expr2[1]).unwrap().0;
+                if expr1[..7] != expr2[..7] {
+                    let vec1 = &state.container_values().get_val::<VecContainer>(expr1[6]).unwrap().0;
+                    let vec2 = &state.container_values().get_val::<VecContainer>(expr2[6]).unwrap().0;
                     assert_eq!(vec1.len(), vec2.len());
                     for (elem1, elem2) in vec1.iter().zip(vec2.iter()) {    // union each pair of elements
                         if elem1 != elem2 {
-                            state.stage_insert(displaced, &[*elem1, *elem2, expr2[2]]);
+                            state.stage_insert(displaced, &[*elem1, *elem2, expr2[7]]);
                         }
                     }
                     res.extend_from_slice(expr2);   // expr2 wins
                     true
                 }
                 else {
-                    false
+                    false   // only the timestamp differs, the older row stays
                 }
             })
         );
         let dffs = db.add_table(dffs_impl, iter::once(displaced), iter::once(displaced));
 
+        // (sig, ins, outs, t), where ins and outs are vecs of wirevecs in the port order of sig
+        // the outputs are part of the key, so two opaque cells are only merged if they are identical
+        let opaque_cells_impl = SortedWritesTable::new(
+            3, 4, Some(ColumnId::new(3)), vec![ColumnId::new(1), ColumnId::new(2)],
+            Box::new(|_, _, _, _| false)
+        );
+        let opaque_cells = db.add_table(opaque_cells_impl, iter::once(displaced), iter::once(displaced));
+
+        // (vec, word, t), the value of a constant wirevec as an i64 base value
+        let const_words_impl = SortedWritesTable::new(
+            1, 3, Some(ColumnId::new(2)), vec![ColumnId::new(0)],
+            Box::new(|_, _, _, _| false)
+        );
+        let const_words = db.add_table(const_words_impl, iter::once(displaced), iter::once(displaced));
+
         let mut types = BiHashMap::new();
         for ty in Self::AY_TYPES.iter().chain(Self::ABY_TYPES.iter()).chain(Self::ABSY_TYPES.iter()) {
             types.insert(ty.to_string(), Value::from_usize(db.inc_counter(id_counter)));
         }
 
+        // constant bits come first, so they have the smallest ids and represent their classes after unions
+        let mut wires = BiHashMap::new();
+        for (_, wire) in Self::CONST_BITS {
+            wires.insert(*wire, Value::from_usize(db.inc_counter(id_counter)));
+        }
+        let empty_vec = db.with_execution_state(|state| {
+            state.container_values().register_val(VecContainer(Vec::new()), state)
+        });
+        let funcs = Self::register_external_funcs(&mut db, id_counter, ts_counter, displaced, &types, *wires.get_by_left(&0).unwrap(), *wires.get_by_left(&1).unwrap());
+
         Self{
             db, id_counter, ts_counter,
-            displaced, ay_cells, aby_cells, absy_cells, dffs,
-            types, wires: BiHashMap::new(), clk: -1, from_inputs: HashMap::new(), as_outputs: HashMap::new()
+            displaced, ay_cells, aby_cells, absy_cells, dffs, opaque_cells, const_words, const_words_from: 0,
+            types, wires, netnames: Vec::new(), wire_names: HashMap::new(), opaque_sigs: BiHashMap::new(), reg_kinds: BiHashMap::new(), empty_vec, funcs, clks: Vec::new(), clks_declared: false, from_inputs: HashMap::new(), as_outputs: HashMap::new(), progress: None, staged: Vec::new()
         }
     }
 
-    pub fn build_from_json(&mut self, json_path: &str, top_mod: &str, clk_name: &str) {
-        let data = std::fs::read_to_string(json_path)
-            .expect("Failed to read JSON file");
-        let netlist: serde_json::Value = serde_json::from_str(&data).unwrap();
-        println!("Successfully loaded JSON netlist from {}", json_path);
-        let top_module = netlist.get("modules")
-            .and_then(|m| m.get(top_mod))
-            .expect("Top module not found");
-        self.build_mod(top_module, clk_name);
-    }
-
-    fn build_ay_cell<'a>(&mut self, cell: &'a serde_json::Value, ts: Value) -> Option<&'a serde_json::Value> {
+    fn build_ay_cell<'a>(&mut self, cell: &'a yosys::Cell, ts: Value, loc: &Location) -> Result<Option<&'a yosys::Cell>, NetlistError> {
         // return None if the cell is processed
-        let cell_type = cell.get("type").and_then(|d| d.as_str()).unwrap();
+        let cell_type = cell.ty.as_str();
         if !Self::RTLIL_AY_TYPES.contains(&cell_type) {
-            Some(cell)
+            Ok(Some(cell))
         }
         else {
-            let conns = cell.get("connections").and_then(|d| d.as_object()).unwrap();
-            self.db
-                .get_table(self.ay_cells)
-                .new_buffer().stage_insert(&[
-                    *self.types.get_by_left(cell_type).unwrap(),
-                    self.create_or_lookup_wirevec_from_json(conns.get("A").unwrap()),
-                    self.create_or_lookup_wirevec_from_json(conns.get("Y").unwrap()),
-                    ts
-                ]);
-            None
+            let cell_type = match Self::RTLIL_AY_SIGNED_TYPES.contains(&cell_type) {
+                true => {
+                    let params = &cell.parameters;
+                    match Self::bool_param(params, "A_SIGNED", loc)? {
+                        true => [cell_type, "s"].concat(),
+                        false => [cell_type, "u"].concat()
+                    }
+                },
+                false => cell_type.to_string()  // reductions and $logic_not only look at whether bits are set
+            };
+            let row = vec![
+                Staged::Val(self.type_value(&cell_type, loc)?),
+                self.stage_wirevec(Self::sized_port(cell, "A", "A_WIDTH", loc)?, loc)?,
+                self.stage_wirevec(Self::sized_port(cell, "Y", "Y_WIDTH", loc)?, loc)?,
+                Staged::Val(ts)
+            ];
+            self.stage_row(self.ay_cells, row);
+            Ok(None)
         }
     }
 
-    fn build_aby_arith_cell<'a>(&mut self, cell: &'a serde_json::Value, ts: Value) -> Option<&'a serde_json::Value> {
+    fn build_aby_arith_cell<'a>(&mut self, cell: &'a yosys::Cell, ts: Value, loc: &Location) -> Result<Option<&'a yosys::Cell>, NetlistError> {
         // return None if the cell is processed
-        let cell_type = cell.get("type").and_then(|d| d.as_str()).unwrap();
+        let cell_type = cell.ty.as_str();
         if !Self::RTLIL_ABY_ARITH_TYPES.contains(&cell_type) {
-            Some(cell)
+            Ok(Some(cell))
         }
         else {
-            let params = cell.get("parameters").and_then(|d| d.as_object()).unwrap();
-            let conns = cell.get("connections").and_then(|d| d.as_object()).unwrap();
-            let a_signed = params.get("A_SIGNED")
-                .map(|d| Self::param_to_i64(d) != 0)
-                .unwrap_or(false);
-            let b_signed = params.get("B_SIGNED")
-                .map(|d| Self::param_to_i64(d) != 0)
-                .unwrap_or(false);
+            let params = &cell.parameters;
+            let a_signed = Self::bool_param(params, "A_SIGNED", loc)?;
+            let b_signed = Self::bool_param(params, "B_SIGNED", loc)?;
             let cell_type = match a_signed && b_signed {
                 true => [cell_type, "s"].concat(),
                 false => [cell_type, "u"].concat()
             };
 
-            self.db
-                .get_table(self.aby_cells)
-                .new_buffer().stage_insert(&[
-                    *self.types.get_by_left(&cell_type).unwrap(),
-                    self.create_or_lookup_wirevec_from_json(conns.get("A").unwrap()),
-                    self.create_or_lookup_wirevec_from_json(conns.get("B").unwrap()),
-                    self.create_or_lookup_wirevec_from_json(conns.get("Y").unwrap()),
-                    ts
-                ]);
-            None
+            let row = vec![
+                Staged::Val(self.type_value(&cell_type, loc)?),
+                self.stage_wirevec(Self::sized_port(cell, "A", "A_WIDTH", loc)?, loc)?,
+                self.stage_wirevec(Self::sized_port(cell, "B", "B_WIDTH", loc)?, loc)?,
+                self.stage_wirevec(Self::sized_port(cell, "Y", "Y_WIDTH", loc)?, loc)?,
+                Staged::Val(ts)
+            ];
+            self.stage_row(self.aby_cells, row);
+            Ok(None)
         }
     }
 
-    fn build_aby_logic_cell<'a>(&mut self, cell: &'a serde_json::Value, ts: Value) -> Option<&'a serde_json::Value> {
+    fn build_aby_logic_cell<'a>(&mut self, cell: &'a yosys::Cell, ts: Value, loc: &Location) -> Result<Option<&'a yosys::Cell>, NetlistError> {
         // return None if the cell is processed
-        let cell_type = cell.get("type").and_then(|d| d.as_str()).unwrap();
-        if !Self::RTLIL_ABY_LOGIC_TYPES.contains(&cell_type) {
-            Some(cell)
+        let cell_type = cell.ty.as_str();
+        if !Self::RTLIL_ABY_LOGIC_TYPES.contains(&cell_type) && !Self::RTLIL_ABY_BOOL_TYPES.contains(&cell_type) {
+            Ok(Some(cell))
         }
         else {
-            let conns = cell.get("connections").and_then(|d| d.as_object()).unwrap();
-            self.db
-                .get_table(self.aby_cells)
-                .new_buffer().stage_insert(&[
-                    *self.types.get_by_left(cell_type).unwrap(),
-                    self.create_or_lookup_wirevec_from_json(conns.get("A").unwrap()),
-                    self.create_or_lookup_wirevec_from_json(conns.get("B").unwrap()),
-                    self.create_or_lookup_wirevec_from_json(conns.get("Y").unwrap()),
-                    ts
-                ]);
-            None
+            let cell_type = match Self::RTLIL_ABY_LOGIC_TYPES.contains(&cell_type) {
+                true => {
+                    // operands are extended to a common width, by their sign only if both are signed
+                    let params = &cell.parameters;
+                    match Self::bool_param(params, "A_SIGNED", loc)? && Self::bool_param(params, "B_SIGNED", loc)? {
+                        true => [cell_type, "s"].concat(),
+                        false => [cell_type, "u"].concat()
+                    }
+                },
+                false => cell_type.to_string()
+            };
+            let row = vec![
+                Staged::Val(self.type_value(&cell_type, loc)?),
+                self.stage_wirevec(Self::sized_port(cell, "A", "A_WIDTH", loc)?, loc)?,
+                self.stage_wirevec(Self::sized_port(cell, "B", "B_WIDTH", loc)?, loc)?,
+                self.stage_wirevec(Self::sized_port(cell, "Y", "Y_WIDTH", loc)?, loc)?,
+                Staged::Val(ts)
+            ];
+            self.stage_row(self.aby_cells, row);
+            Ok(None)
         }
     }
 
-    fn build_absy_cell<'a>(&mut self, cell: &'a serde_json::Value, ts: Value) -> Option<&'a serde_json::Value> {
+    fn build_aby_shift_cell<'a>(&mut self, cell: &'a yosys::Cell, ts: Value, loc: &Location) -> Result<Option<&'a yosys::Cell>, NetlistError> {
         // return None if the cell is processed
-        let cell_type = cell.get("type").and_then(|d| d.as_str()).unwrap();
+        let cell_type = cell.ty.as_str();
+        if !Self::RTLIL_ABY_SHIFT_TYPES.contains(&cell_type) {
+            return Ok(Some(cell));
+        }
+        let params = &cell.parameters;
+        let a_signed = Self::bool_param(params, "A_SIGNED", loc)?;
+        let b_signed = Self::bool_param(params, "B_SIGNED", loc)?;
+        if b_signed {
+            // only $shift and $shiftx honor a signed (possibly negative) shift amount, keep those opaque
+            return Ok(Some(cell));
+        }
+        // unlike arithmetic cells, only the signedness of A matters
+        let cell_type = match a_signed {
+            true => [cell_type, "s"].concat(),
+            false => [cell_type, "u"].concat()
+        };
+
+        let row = vec![
+            Staged::Val(self.type_value(&cell_type, loc)?),
+            self.stage_wirevec(Self::sized_port(cell, "A", "A_WIDTH", loc)?, loc)?,
+            self.stage_wirevec(Self::sized_port(cell, "B", "B_WIDTH", loc)?, loc)?,
+            self.stage_wirevec(Self::sized_port(cell, "Y", "Y_WIDTH", loc)?, loc)?,
+            Staged::Val(ts)
+        ];
+        self.stage_row(self.aby_cells, row);
+        Ok(None)
+    }
+
+    fn build_absy_cell<'a>(&mut self, cell: &'a yosys::Cell, ts: Value, loc: &Location) -> Result<Option<&'a yosys::Cell>, NetlistError> {
+        // return None if the cell is processed
+        let cell_type = cell.ty.as_str();
         if !Self::RTLIL_ABSY_TYPES.contains(&cell_type) {
-            Some(cell)
+            Ok(Some(cell))
         }
         else {
-            let conns = cell.get("connections").and_then(|d| d.as_object()).unwrap();
-            self.db
-                .get_table(self.absy_cells)
-                .new_buffer().stage_insert(&[
-                    *self.types.get_by_left(cell_type).unwrap(),
-                    self.create_or_lookup_wirevec_from_json(conns.get("A").unwrap()),
-                    self.create_or_lookup_wirevec_from_json(conns.get("B").unwrap()),
-                    self.create_or_lookup_wirevec_from_json(conns.get("S").unwrap()),
-                    self.create_or_lookup_wirevec_from_json(conns.get("Y").unwrap()),
-                    ts
-                ]);
-            None
-        }
-    }
-
-    fn build_dff_cell<'a>(&mut self, cell: &'a serde_json::Value, ts: Value) -> Option<&'a serde_json::Value> {
+            let conns = &cell.connections;
+            let row = vec![
+                Staged::Val(self.type_value(cell_type, loc)?),
+                self.stage_wirevec(Self::sized_port(cell, "A", "WIDTH", loc)?, loc)?,
+                self.stage_wirevec(Self::sized_port(cell, "B", "WIDTH", loc)?, loc)?,
+                self.stage_wirevec(Self::port(conns, "S", loc)?, loc)?,
+                self.stage_wirevec(Self::sized_port(cell, "Y", "WIDTH", loc)?, loc)?,
+                Staged::Val(ts)
+            ];
+            self.stage_row(self.absy_cells, row);
+            Ok(None)
+        }
+    }
+
+    fn build_reg_cell<'a>(&mut self, cell: &'a yosys::Cell, ts: Value, loc: &Location) -> Result<Option<&'a yosys::Cell>, NetlistError> {
         // return None if the cell is processed
-        let cell_type = cell.get("type").and_then(|d| d.as_str()).unwrap();
-        if cell_type != "$dff" {
-            Some(cell)
+        let cell_type = cell.ty.as_str();
+        let Some(&(_, clk_port, en_port, rst_port, rst_val_port)) = Self::RTLIL_REG_TYPES.iter().find(|r| r.0 == cell_type) else {
+            return Ok(Some(cell));
+        };
+        let params = &cell.parameters;
+        let conns = &cell.connections;
+
+        let mut polarities = Vec::new();
+        for name in params.keys().filter(|n| n.ends_with("_POLARITY")) {
+            polarities.push((name.as_str(), Self::bool_param(params, name, loc)?));
         }
-        else {
-            let conns = cell.get("connections").and_then(|d| d.as_object()).unwrap();
-            let clk = conns.get("CLK").and_then(|d| d.as_array()).and_then(|a| a.iter().map(Self::bit_to_i64).collect::<Vec<_>>().into()).unwrap();
-            if clk.len() != 1 || clk[0] != self.clk {
-                panic!("DFF cell clock must be the same as the global clock");
+        let kind = RegKind::new(cell_type, &polarities);
+
+        let clk = match clk_port {
+            "" => Staged::Val(self.empty_vec),
+            port => {
+                let clk = Self::bits_to_i64(Self::port(conns, port, loc)?, loc)?;
+                if clk.len() != 1 {
+                    return Err(NetlistError::ClockWidth { loc: loc.clone(), port: port.to_string(), width: clk.len() });
+                }
+                if !self.clks.contains(&clk[0]) {
+                    if self.clks_declared {
+                        return Err(NetlistError::ClockMismatch { loc: loc.clone(), expected: self.clks.clone(), found: clk });
+                    }
+                    self.clks.push(clk[0]);
+                }
+                self.stage_wirevec(Self::port(conns, port, loc)?, loc)?
+            }
+        };
+        let en = match en_port {
+            "" => Staged::Val(self.empty_vec),
+            port => self.stage_wirevec(Self::port(conns, port, loc)?, loc)?
+        };
+        let rst = match rst_port {
+            "" => Staged::Val(self.empty_vec),
+            port => self.stage_wirevec(Self::port(conns, port, loc)?, loc)?
+        };
+        let rst_val = match rst_val_port {
+            "" => Staged::Val(self.empty_vec),
+            param if param.ends_with("_VALUE") => {
+                // reset values are parameters, keep them as constant wirevecs
+                let width = Self::param_to_i64(params.get("WIDTH").ok_or_else(|| NetlistError::MissingField { loc: loc.clone(), field: "WIDTH".to_string() })?, "WIDTH", loc)?;
+                let value = params.get(param).ok_or_else(|| NetlistError::MissingField { loc: loc.clone(), field: param.to_string() })?;
+                let vec = Self::param_to_bits(value, width as usize, param, loc)?
+                    .into_iter()
+                    .map(|b| self.create_or_lookup_wire(b))
+                    .collect();
+                Staged::Vec(vec)
+            },
+            port => self.stage_wirevec(Self::port(conns, port, loc)?, loc)?
+        };
+
+        let row = vec![
+            Staged::Val(self.create_or_lookup_reg_kind(kind)),
+            clk, en, rst, rst_val,
+            self.stage_wirevec(Self::port(conns, "D", loc)?, loc)?,
+            self.stage_wirevec(Self::port(conns, "Q", loc)?, loc)?,
+            Staged::Val(ts)
+        ];
+        self.stage_row(self.dffs, row);
+        Ok(None)
+    }
+
+    fn build_opaque_cell<'a>(&mut self, cell: &'a yosys::Cell, ts: Value, loc: &Location) -> Result<Option<&'a yosys::Cell>, NetlistError> {
+        // keeps any cell as an uninterpreted row, so it always returns None
+        let cell_type = cell.ty.as_str();
+        let conns = &cell.connections;
+        let params = serde_json::to_string(&cell.parameters).expect("Parameters are serializable");
+        let directions = cell.port_directions.as_ref();
+        let mut sig = OpaqueSig { ty: cell_type.to_string(), params, inputs: Vec::new(), outputs: Vec::new() };
+        let (mut ins, mut outs) = (Vec::new(), Vec::new());
+        for (port, bits) in conns.iter() {
+            let is_output = match directions.and_then(|d| d.get(port)).map(String::as_str) {
+                Some("input") => false,
+                Some("output") => true,
+                Some(direction) => return Err(NetlistError::UnknownDirection { loc: loc.clone(), port: port.clone(), direction: direction.to_string() }),
+                None => Self::OPAQUE_OUTPUT_PORTS.contains(&port.as_str())
+            };
+            let vec = self.create_or_lookup_wires(bits, loc)?;
+            match is_output {
+                true => { sig.outputs.push(port.clone()); outs.push(vec); },
+                false => { sig.inputs.push(port.clone()); ins.push(vec); }
             }
-            self.db
-                .get_table(self.dffs)
-                .new_buffer().stage_insert(&[
-                    self.create_or_lookup_wirevec_from_json(conns.get("D").unwrap()),
-                    self.create_or_lookup_wirevec_from_json(conns.get("Q").unwrap()),
-                    ts
-                ]);
-            None
         }
+        debug!("Keeping cell {} of type {} as an opaque cell", loc.cell.as_deref().unwrap_or(""), cell_type);
+
+        let row = vec![
+            Staged::Val(self.create_or_lookup_opaque_sig(sig)),
+            Staged::Vecs(ins),
+            Staged::Vecs(outs),
+            Staged::Val(ts)
+        ];
+        self.stage_row(self.opaque_cells, row);
+        Ok(None)
     }
 
-    pub fn build_mod(&mut self, top_mod: &serde_json::Value, clk_name: &str) {
-        let start = Instant::now();
+    /// Imports a cell of a type the tables know, handing it back if no builder takes it:
+    /// then it is an instance of another module or has to stay an opaque cell.
+    pub(crate) fn build_cell<'a>(&mut self, cell: &'a yosys::Cell, loc: &Location) -> Result<Option<&'a yosys::Cell>, NetlistError> {
+        // chain of cell processing functions
+        let mut res = Some(cell);
+        for build in Self::CELL_BUILDERS {
+            match res {
+                Some(c) => res = build(self, c, Value::new(0), loc)?,
+                None => break
+            }
+        }
+        Ok(res)
+    }
 
-        // build inputs & outputs
-        let ports = top_mod.get("ports").and_then(|d| d.as_object()).unwrap();
+    /// Imports the ports of the top module as inputs and outputs, some of the inputs being the named clocks.
+    pub(crate) fn build_ports(&mut self, mod_name: &str, ports: &BTreeMap<String, yosys::Port>, clk_names: &[&str]) -> Result<(), NetlistError> {
+        let mod_loc = Location::module(mod_name);
+        // a clock has to be an input port, an output of the same name would never drive a register
+        let is_input = |name: &str| ports.get(name).is_some_and(|port| port.direction == "input");
+        if let Some(clk_name) = clk_names.iter().find(|n| !is_input(**n)) {
+            return Err(NetlistError::MissingClock { loc: mod_loc, port: clk_name.to_string() });
+        }
+        self.clks_declared |= !clk_names.is_empty();
         for (name, port) in ports.iter() {
-            let direction = port
-                .get("direction")
-                .and_then(|d| d.as_str())
-                .unwrap();
-            let bits = port
-                .get("bits")
-                .and_then(|d| d.as_array())
-                .and_then(|a| a.iter()
-                    .map(Self::bit_to_i64)
-                    .collect::<Vec<_>>()
-                    .into()
-                )
-                .unwrap();
-            match direction {
+            let bits = Self::bits_to_i64(&port.bits, &mod_loc)?;
+            match port.direction.as_str() {
                 "input" => {
-                    if name == clk_name {
+                    if clk_names.contains(&name.as_str()) {
                         if bits.len() != 1 {
-                            panic!("Clock port must have exactly one bit");
+                            return Err(NetlistError::ClockWidth { loc: mod_loc, port: name.clone(), width: bits.len() });
                         }
-                        self.clk = bits[0];
+                        self.clks.push(bits[0]);
                     }
                     for (i, bit) in bits.iter().enumerate() {
                         self.from_inputs.insert((name.clone(), i as i64), *bit);
-                        self.create_wire(*bit);
+                        self.create_or_lookup_wire(*bit);
                     }
                 },
                 "output" => {
                     for (i, bit) in bits.iter().enumerate() {
                         self.as_outputs.insert((name.clone(), i as i64), *bit);
-                        self.create_wire(*bit);
+                        self.create_or_lookup_wire(*bit);
                     }
                 },
-                _ => panic!("Unknown port direction: {}", direction)
+                direction => return Err(NetlistError::UnknownDirection { loc: mod_loc, port: name.clone(), direction: direction.to_string() })
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn build_netname(&mut self, mod_name: &str, name: &str, netname: &yosys::Netname) -> Result<(), NetlistError> {
+        let bits = Self::bits_to_i64(&netname.bits, &Location::module(mod_name))?;
+        let hide_name = match netname.hide_name {
+            Some(hide) => hide != 0,
+            None => name.starts_with('$')
+        };
+        for (i, bit) in bits.iter().enumerate() {
+            self.create_or_lookup_wire(*bit);
+            self.wire_names.entry(*bit).or_default().push((self.netnames.len(), i));
+        }
+        self.netnames.push(NetName { name: name.to_string(), hide_name, src: netname.src().map(str::to_string), bits });
+        Ok(())
+    }
+
+    /// Imports the cells no builder took: instances of `modules` are inlined or kept according to
+    /// `hierarchy`, cells of any other type become opaque cells. Call it once the ports, netnames
+    /// and the other cells are imported, as inlined nets get ids above every id seen so far.
+    pub(crate) fn build_instances(
+        &mut self, mod_name: &str, cells: &[(&str, &yosys::Cell)], modules: &BTreeMap<String, yosys::Module>, hierarchy: Hierarchy
+    ) -> Result<(), NetlistError> {
+        let mut next_id = self.wires.left_values().copied()
+            .chain(cells.iter().flat_map(|(_, c)| c.connections.values().flatten().filter_map(|b| b.net())))
+            .max()
+            .unwrap_or(1) + 1;
+        let mut flattened = Flattened::default();
+        for (name, cell) in cells.iter() {
+            let loc = Location::cell(mod_name, name, cell.src());
+            // a blackbox has no cells to inline, so its instances stay opaque either way
+            match (modules.get(&cell.ty), hierarchy) {
+                (Some(module), Hierarchy::Flatten) if !Self::is_blackbox(module, &Location::module(&cell.ty))? => {
+                    let mut stack = vec![mod_name.to_string()];
+                    Self::flatten_instance(modules, name, cell, &loc, &mut next_id, &mut stack, &mut flattened)?;
+                },
+                (Some(module), _) => {
+                    self.build_opaque_cell(&Self::with_port_directions(cell, module), Value::new(0), &loc)?;
+                },
+                _ => {
+                    self.build_opaque_cell(cell, Value::new(0), &loc)?;
+                }
             }
         }
 
-        // build cells
-        let cells = top_mod.get("cells").and_then(|d| d.as_object()).unwrap();
-        println!("Found {} cells to process", cells.len());
-        for (i, (name, cell)) in cells.iter().enumerate() {
-            if i % 1000 == 0 {
-                println!("Processing cell {}/{}: {}", i, cells.len(), name);
+        for (name, netname) in flattened.netnames.iter() {
+            self.build_netname(mod_name, name, netname)?;
+        }
+        for (name, cell) in flattened.cells.iter() {
+            let loc = Location::cell(mod_name, name, cell.src());
+            if let Some(cell) = self.build_cell(cell, &loc)? {
+                self.build_opaque_cell(cell, Value::new(0), &loc)?;
             }
+        }
+        Ok(())
+    }
 
-            // chain of cell processing functions
-            let res = self.build_ay_cell(cell, Value::new(0))
-                .and_then(|c| self.build_aby_arith_cell(c, Value::new(0)))
-                .and_then(|c| self.build_aby_logic_cell(c, Value::new(0)))
-                .and_then(|c| self.build_absy_cell(c, Value::new(0)))
-                .and_then(|c| self.build_dff_cell(c, Value::new(0)));
-            if let Some(_) = res {
-                println!("Unprocessed cell: {}", name);
+    /// Cells imported per second since `start`.
+    pub(crate) fn throughput(cells: usize, start: Instant) -> f64 {
+        cells as f64 / start.elapsed().as_secs_f64().max(1e-9)
+    }
+
+    pub(crate) fn finish_mod(&mut self, mod_name: &str, cells_done: usize, start: Instant) {
+        self.flush_staged();
+        self.merge_all();
+        let cells_per_sec = Self::throughput(cells_done, start);
+        self.report(Progress::Import { module: mod_name.to_string(), cells_done, cells_total: Some(cells_done), percent: 100.0, cells_per_sec });
+        info!("Imported {} cells at {:.0} cells/s", cells_done, cells_per_sec);
+        info!("Found {} constant wirevec(s)", self.index_const_words());
+        info!("Found {} clock domain(s)", self.clks.len());
+        info!("Built module {} in {} ms", mod_name, start.elapsed().as_millis());
+    }
+
+    /// Imports `mod_name` of a netlist parsed as a whole, see `build_from_json` for the other arguments.
+    pub fn build_mod(&mut self, mod_name: &str, modules: &BTreeMap<String, yosys::Module>, clk_names: &[&str], hierarchy: Hierarchy) -> Result<(), NetlistError> {
+        let start = Instant::now();
+        let module = modules.get(mod_name).ok_or_else(|| NetlistError::MissingModule { module: mod_name.to_string() })?;
+        self.build_ports(mod_name, &module.ports, clk_names)?;
+        for (name, netname) in module.netnames.iter() {
+            self.build_netname(mod_name, name, netname)?;
+        }
+
+        // build cells, leaving instances of other modules for last
+        let total = module.cells.len();
+        info!("Found {} cells to process", total);
+        let step = total.div_ceil(100).max(1);   // report about every percent
+        let mut others = Vec::new();
+        for (i, (name, cell)) in module.cells.iter().enumerate() {
+            trace!("Processing cell {}/{}: {}", i, total, name);
+            if i % step == 0 {
+                self.report(Progress::Import {
+                    module: mod_name.to_string(),
+                    cells_done: i,
+                    cells_total: Some(total),
+                    percent: 100.0 * i as f64 / total as f64,
+                    cells_per_sec: Self::throughput(i, start)
+                });
+            }
+            let loc = Location::cell(mod_name, name, cell.src());
+            if let Some(cell) = self.build_cell(cell, &loc)? {
+                others.push((name.as_str(), cell));
             }
         }
+        self.build_instances(mod_name, &others, modules, hierarchy)?;
 
-        println!("Successfully built top module in {} ms", start.elapsed().as_millis());
+        self.finish_mod(mod_name, total, start);
+        Ok(())
     }
-}
\ No newline at end of file
+}
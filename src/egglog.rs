@@ -0,0 +1,122 @@
+use core_relations::Value;
+use std::fmt::Write;
+use crate::core::NetlistDatabase;
+
+
+/// Wires, then buses (wirevecs) built from them and the cells over buses.
+/// Registers take their kind, clk, en, rst and rst_val before d; unused controls are empty buses.
+const EGGLOG_PRELUDE: &str = "\
+(datatype Wire (Net i64) (Const String) (InputBit String i64) (OutputBit String i64))
+(sort WireVec (Vec Wire))
+(datatype Bus
+  (Bits WireVec)
+  (Ay String Bus)
+  (Aby String Bus Bus)
+  (Absy String Bus Bus Bus)
+  (Reg String Bus Bus Bus Bus Bus))
+";
+
+/// Quotes `s` as an egglog string literal, escaping quotes, backslashes and control characters.
+fn egglog_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            c => out.push(c)
+        }
+    }
+    out.push('"');
+    out
+}
+
+impl NetlistDatabase {
+    // auxiliary functions
+    fn egglog_reg_kind(&self, kind: Value) -> String {
+        let kind = self.reg_kinds.get_by_right(&kind).expect("Unknown register kind");
+        let mut name = kind.ty.clone();
+        for (param, polarity) in kind.polarities.iter() {
+            write!(name, " {}={}", param, *polarity as u8).unwrap();
+        }
+        name
+    }
+
+    /// Writes the cell tables as an egglog program, with wires named by their canonical ids.
+    /// Each row becomes a `union` of a cell term with its output bus; opaque cells are left out.
+    pub fn export_egglog(&self) -> String {
+        let canon = self.canon_map();
+        let wirevecs = self.wirevecs();
+        let canon_of = |v: Value| *canon.get(&v).unwrap_or(&v);
+        let bus = |vec: Value| -> String {
+            let wires = wirevecs.get(&vec)
+                .or_else(|| wirevecs.get(&canon_of(vec)))
+                .expect("Wirevec not found in container values");
+            match wires.is_empty() {
+                true => "(Bits (vec-empty))".to_string(),
+                false => {
+                    let nets: Vec<String> = wires.iter().map(|w| format!("(Net {})", canon_of(*w).rep())).collect();
+                    format!("(Bits (vec-of {}))", nets.join(" "))
+                }
+            }
+        };
+        let ty = |t: Value| self.types.get_by_right(&t).expect("Unknown cell type");
+
+        let mut out = String::from(EGGLOG_PRELUDE);
+
+        // constants and ports
+        writeln!(out).unwrap();
+        let mut named: Vec<(String, i64)> = Vec::new();
+        for (c, wire) in Self::CONST_BITS {
+            named.push((format!("(Const {})", egglog_string(&c.to_string())), *wire));
+        }
+        let mut ports: Vec<_> = self.from_inputs.iter().map(|(k, w)| ("InputBit", k, *w))
+            .chain(self.as_outputs.iter().map(|(k, w)| ("OutputBit", k, *w)))
+            .collect();
+        ports.sort();
+        for (ctor, (name, i), wire) in ports {
+            named.push((format!("({} {} {})", ctor, egglog_string(name), i), wire));
+        }
+        for (term, wire) in named {
+            if let Some(val) = self.wires.get_by_left(&wire) {
+                writeln!(out, "(union {} (Net {}))", term, canon_of(*val).rep()).unwrap();
+            }
+        }
+
+        // (type, a, y, t)
+        writeln!(out).unwrap();
+        for row in self.table_rows(self.ay_cells) {
+            writeln!(out, "(union (Ay {} {}) {})", egglog_string(ty(row[0])), bus(row[1]), bus(row[2])).unwrap();
+        }
+        // (type, a, b, y, t)
+        for row in self.table_rows(self.aby_cells) {
+            writeln!(out, "(union (Aby {} {} {}) {})", egglog_string(ty(row[0])), bus(row[1]), bus(row[2]), bus(row[3])).unwrap();
+        }
+        // (type, a, b, s, y, t)
+        for row in self.table_rows(self.absy_cells) {
+            writeln!(out, "(union (Absy {} {} {} {}) {})", egglog_string(ty(row[0])), bus(row[1]), bus(row[2]), bus(row[3]), bus(row[4])).unwrap();
+        }
+        // (kind, clk, en, rst, rst_val, d, q, t)
+        for row in self.table_rows(self.dffs) {
+            let ins: Vec<String> = row[1..6].iter().map(|v| bus(*v)).collect();
+            writeln!(out, "(union (Reg {} {}) {})", egglog_string(&self.egglog_reg_kind(row[0])), ins.join(" "), bus(row[6])).unwrap();
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_quotes_backslashes_and_control_characters() {
+        assert_eq!(egglog_string("$add"), r#""$add""#);
+        assert_eq!(egglog_string("a\"b\\c"), r#""a\"b\\c""#);
+        assert_eq!(egglog_string("l1\nl2\tx\r"), r#""l1\nl2\tx\r""#);
+    }
+}
@@ -0,0 +1,147 @@
+use std::fmt;
+
+
+/// Where in the input netlist a problem was found.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Location {
+    pub module: String,
+    pub cell: Option<String>,
+    pub src: Option<String>   // Yosys `src` attribute of the cell, if any
+}
+
+impl Location {
+    pub fn module(module: &str) -> Self {
+        Self { module: module.to_string(), cell: None, src: None }
+    }
+
+    pub fn cell(module: &str, cell: &str, src: Option<&str>) -> Self {
+        Self { module: module.to_string(), cell: Some(cell.to_string()), src: src.map(str::to_string) }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "module `{}`", self.module)?;
+        if let Some(cell) = &self.cell {
+            write!(f, ", cell `{}`", cell)?;
+        }
+        if let Some(src) = &self.src {
+            write!(f, " ({})", src)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum NetlistError {
+    Io { path: String, source: std::io::Error },
+    Json { path: String, source: serde_json::Error },
+    MissingModule { module: String },
+    MissingField { loc: Location, field: String },
+    MissingPort { loc: Location, port: String },
+    BadBit { loc: Location, bit: serde_json::Value },
+    BadParam { loc: Location, param: String, value: serde_json::Value },
+    UnknownDirection { loc: Location, port: String, direction: String },
+    UnknownType { loc: Location, ty: String },
+    MissingClock { loc: Location, port: String },
+    ClockWidth { loc: Location, port: String, width: usize },
+    ClockMismatch { loc: Location, expected: Vec<i64>, found: Vec<i64> },
+    PortWidth { loc: Location, port: String, expected: usize, found: usize },
+    RecursiveModule { loc: Location, module: String }
+}
+
+impl fmt::Display for NetlistError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetlistError::Io { path, source } => write!(f, "failed to read {}: {}", path, source),
+            NetlistError::Json { path, source } => write!(f, "failed to parse {}: {}", path, source),
+            NetlistError::MissingModule { module } => write!(f, "module `{}` not found", module),
+            NetlistError::MissingField { loc, field } => write!(f, "{}: missing field `{}`", loc, field),
+            NetlistError::MissingPort { loc, port } => write!(f, "{}: missing connection for port `{}`", loc, port),
+            NetlistError::BadBit { loc, bit } => write!(f, "{}: invalid bit {}", loc, bit),
+            NetlistError::BadParam { loc, param, value } => write!(f, "{}: invalid value {} for parameter `{}`", loc, value, param),
+            NetlistError::UnknownDirection { loc, port, direction } => write!(f, "{}: unknown direction `{}` of port `{}`", loc, direction, port),
+            NetlistError::UnknownType { loc, ty } => write!(f, "{}: unknown cell type `{}`", loc, ty),
+            NetlistError::MissingClock { loc, port } => write!(f, "{}: no input port `{}` for the clock", loc, port),
+            NetlistError::ClockWidth { loc, port, width } => write!(f, "{}: clock port `{}` has {} bits, expected 1", loc, port, width),
+            NetlistError::ClockMismatch { loc, expected, found } => write!(f, "{}: clocked by {:?}, expected one of the declared clocks {:?}", loc, found, expected),
+            NetlistError::PortWidth { loc, port, expected, found } => write!(f, "{}: port `{}` has {} bits connected, expected {}", loc, port, found, expected),
+            NetlistError::RecursiveModule { loc, module } => write!(f, "{}: module `{}` instantiates itself", loc, module)
+        }
+    }
+}
+
+impl std::error::Error for NetlistError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            NetlistError::Io { source, .. } => Some(source),
+            NetlistError::Json { source, .. } => Some(source),
+            _ => None
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum VerilogError {
+    UnmappableTypes { types: Vec<String> }  // sorted, each once
+}
+
+impl fmt::Display for VerilogError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerilogError::UnmappableTypes { types } => {
+                let types: Vec<_> = types.iter().map(|ty| format!("`{}`", ty)).collect();
+                write!(f, "no Verilog form for cell type(s) {}", types.join(", "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for VerilogError {}
+
+/// A line and column (both from 1) in a rule file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Pos {
+    pub line: usize,
+    pub col: usize
+}
+
+impl fmt::Display for Pos {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.col)
+    }
+}
+
+#[derive(Debug)]
+pub enum RuleError {
+    Io { path: String, source: std::io::Error },
+    Parse { pos: Pos, msg: String },
+    UnknownOp { pos: Pos, op: String },
+    UnknownGuard { pos: Pos, guard: String },
+    Arity { pos: Pos, op: String, expected: usize, found: usize },
+    UnboundVar { pos: Pos, var: String },
+    BareLhs { pos: Pos }
+}
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuleError::Io { path, source } => write!(f, "failed to read {}: {}", path, source),
+            RuleError::Parse { pos, msg } => write!(f, "{}: {}", pos, msg),
+            RuleError::UnknownOp { pos, op } => write!(f, "{}: unknown cell type `{}`", pos, op),
+            RuleError::UnknownGuard { pos, guard } => write!(f, "{}: unknown guard `{}`", pos, guard),
+            RuleError::Arity { pos, op, expected, found } => write!(f, "{}: `{}` takes {} operand(s), found {}", pos, op, expected, found),
+            RuleError::UnboundVar { pos, var } => write!(f, "{}: variable `{}` does not occur in the left-hand side", pos, var),
+            RuleError::BareLhs { pos } => write!(f, "{}: the left-hand side must be a cell, not a variable", pos)
+        }
+    }
+}
+
+impl std::error::Error for RuleError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RuleError::Io { source, .. } => Some(source),
+            _ => None
+        }
+    }
+}
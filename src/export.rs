@@ -0,0 +1,365 @@
+use core_relations::Value;
+use serde_json::{json, Map};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use crate::core::{CellRows, NetlistDatabase};
+use crate::param::ParamBits;
+
+
+/// A single bit in an exported netlist, either a constant or a Yosys net id.
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+pub(crate) enum Bit {
+    Const(char),
+    Net(i64)
+}
+
+impl Bit {
+    pub(crate) fn to_json(self) -> serde_json::Value {
+        match self {
+            Bit::Const(c) => json!(c.to_string()),
+            Bit::Net(id) => json!(id)
+        }
+    }
+}
+
+/// Assigns a Yosys bit to every canonical wire, keeping the imported ids where possible.
+pub(crate) struct BitMap {
+    canon: HashMap<Value, Value>,
+    wirevecs: HashMap<Value, Vec<Value>>,
+    bits: HashMap<Value, Bit>,
+    next_id: i64
+}
+
+impl BitMap {
+    pub(crate) fn new(netlist: &NetlistDatabase) -> Self {
+        let canon = netlist.canon_map();
+        let wirevecs = netlist.wirevecs();
+        let mut wires: Vec<(i64, Value)> = netlist.wires.iter().map(|(w, v)| (*w, *v)).collect();
+        wires.sort();
+
+        // constants win over nets, lower ids win over higher ones
+        let mut bits = HashMap::new();
+        for (wire, val) in wires.iter() {
+            let bit = NetlistDatabase::CONST_BITS.iter()
+                .find(|(_, w)| w == wire)
+                .map_or(Bit::Net(*wire), |(c, _)| Bit::Const(*c));
+            let root = *canon.get(val).unwrap_or(val);
+            match (bits.get(&root).copied(), bit) {
+                (Some(Bit::Const(_)), _) | (Some(Bit::Net(_)), Bit::Net(_)) => {},
+                _ => {
+                    bits.insert(root, bit);
+                }
+            }
+        }
+        let next_id = wires.last().map_or(2, |(w, _)| std::cmp::max(w + 1, 2));
+        Self { canon, wirevecs, bits, next_id }
+    }
+
+    pub(crate) fn canon(&self, val: Value) -> Value {
+        *self.canon.get(&val).unwrap_or(&val)
+    }
+
+    pub(crate) fn fresh(&mut self) -> Bit {
+        let bit = Bit::Net(self.next_id);
+        self.next_id += 1;
+        bit
+    }
+
+    pub(crate) fn bit(&mut self, val: Value) -> Bit {
+        let root = self.canon(val);
+        if let Some(bit) = self.bits.get(&root) {
+            return *bit;
+        }
+        let bit = self.fresh();
+        self.bits.insert(root, bit);
+        bit
+    }
+
+    pub(crate) fn wires(&self, vec: Value) -> Vec<Value> {
+        self.wirevecs.get(&vec)
+            .or_else(|| self.wirevecs.get(&self.canon(vec)))
+            .expect("Wirevec not found in container values")
+            .iter()
+            .map(|w| self.canon(*w))
+            .collect()
+    }
+
+    pub(crate) fn wirevec(&mut self, vec: Value) -> Vec<Bit> {
+        self.wires(vec).into_iter().map(|w| self.bit(w)).collect()
+    }
+}
+
+/// A cell lowered back to its RTLIL form, shared by the JSON and Verilog writers.
+pub(crate) struct ExportCell {
+    pub(crate) ty: String,
+    pub(crate) params: Map<String, serde_json::Value>,
+    pub(crate) conns: Vec<(String, bool, Vec<Bit>)>    // (port, is output, bits)
+}
+
+impl ExportCell {
+    fn new(ty: &str, params: &[(&str, i64)], conns: Vec<(&str, bool, Vec<Bit>)>) -> Self {
+        Self {
+            ty: ty.to_string(),
+            params: params.iter().map(|(n, v)| (n.to_string(), NetlistDatabase::param_to_json(*v))).collect(),
+            conns: conns.into_iter().map(|(n, o, b)| (n.to_string(), o, b)).collect()
+        }
+    }
+
+    pub(crate) fn param(&self, name: &str) -> Option<i64> {
+        ParamBits::from_json(self.params.get(name)?)?.to_i64()
+    }
+
+    pub(crate) fn port(&self, name: &str) -> &[Bit] {
+        self.conns.iter().find(|(n, _, _)| *n == name).map(|(_, _, b)| b.as_slice()).unwrap_or(&[])
+    }
+}
+
+/// A module port lowered back to its RTLIL form.
+pub(crate) struct ExportPort {
+    pub(crate) name: String,
+    pub(crate) direction: &'static str,
+    pub(crate) bits: Vec<Bit>
+}
+
+/// A named signal lowered to the bits of the exported netlist.
+pub(crate) struct ExportNetName {
+    pub(crate) name: String,
+    pub(crate) hide_name: bool,
+    pub(crate) src: Option<String>,
+    pub(crate) bits: Vec<Bit>
+}
+
+impl NetlistDatabase {
+    /// Splits an internal type name such as `$adds` into its RTLIL type and signedness.
+    pub(crate) fn rtlil_type(ty: &str) -> (&str, bool) {
+        for (suffix, signed) in [('s', true), ('u', false)] {
+            if let Some(base) = ty.strip_suffix(suffix) {
+                let suffixed = [Self::RTLIL_ABY_ARITH_TYPES, Self::RTLIL_ABY_LOGIC_TYPES, Self::RTLIL_ABY_SHIFT_TYPES, Self::RTLIL_AY_SIGNED_TYPES];
+                if suffixed.iter().any(|types| types.contains(&base)) {
+                    return (base, signed);
+                }
+            }
+        }
+        (ty, false)
+    }
+
+    fn type_name(&self, ty: Value) -> &str {
+        self.types.get_by_right(&ty).expect("Unknown cell type")
+    }
+
+    pub(crate) fn lower_ports(&self, bitmap: &mut BitMap) -> Vec<ExportPort> {
+        let mut ports = Vec::new();
+        for (direction, port_map) in [("input", &self.from_inputs), ("output", &self.as_outputs)] {
+            let mut grouped: BTreeMap<&str, BTreeMap<i64, i64>> = BTreeMap::new();
+            for ((name, i), wire) in port_map.iter() {
+                grouped.entry(name.as_str()).or_default().insert(*i, *wire);
+            }
+            for (name, wires) in grouped {
+                let bits = wires.values()
+                    .map(|w| bitmap.bit(*self.wires.get_by_left(w).expect("Port wire not found")))
+                    .collect();
+                ports.push(ExportPort { name: name.to_string(), direction, bits });
+            }
+        }
+        ports
+    }
+
+    /// Imported netnames other than the ports, on the bits their wires ended up in after unions.
+    pub(crate) fn lower_netnames(&self, ports: &[ExportPort], bitmap: &mut BitMap) -> Vec<ExportNetName> {
+        let mut netnames = Vec::new();
+        for netname in self.netnames.iter().filter(|n| !ports.iter().any(|p| p.name == n.name)) {
+            let bits = netname.bits.iter()
+                .map(|w| bitmap.bit(*self.wires.get_by_left(w).expect("Named wire not found")))
+                .collect();
+            netnames.push(ExportNetName { name: netname.name.clone(), hide_name: netname.hide_name, src: netname.src.clone(), bits });
+        }
+        netnames
+    }
+
+    pub(crate) fn lower_cells(&self, rows: &CellRows, bitmap: &mut BitMap) -> Vec<ExportCell> {
+        let mut cells = Vec::new();
+
+        // (type, a, y, t)
+        for row in rows.ay.iter() {
+            let (ty, signed) = Self::rtlil_type(self.type_name(row[0]));
+            let (a, y) = (bitmap.wirevec(row[1]), bitmap.wirevec(row[2]));
+            cells.push(ExportCell::new(
+                ty,
+                &[("A_SIGNED", signed as i64), ("A_WIDTH", a.len() as i64), ("Y_WIDTH", y.len() as i64)],
+                vec![("A", false, a), ("Y", true, y)]
+            ));
+        }
+
+        // (type, a, b, y, t)
+        for row in rows.aby.iter() {
+            let (ty, signed) = Self::rtlil_type(self.type_name(row[0]));
+            let b_signed = signed && !Self::RTLIL_ABY_SHIFT_TYPES.contains(&ty);   // shift amounts are unsigned
+            let (a, b, y) = (bitmap.wirevec(row[1]), bitmap.wirevec(row[2]), bitmap.wirevec(row[3]));
+            cells.push(ExportCell::new(
+                ty,
+                &[
+                    ("A_SIGNED", signed as i64), ("B_SIGNED", b_signed as i64),
+                    ("A_WIDTH", a.len() as i64), ("B_WIDTH", b.len() as i64), ("Y_WIDTH", y.len() as i64)
+                ],
+                vec![("A", false, a), ("B", false, b), ("Y", true, y)]
+            ));
+        }
+
+        // (type, a, b, s, y, t)
+        for row in rows.absy.iter() {
+            let ty = self.type_name(row[0]);
+            let (a, b, s, y) = (bitmap.wirevec(row[1]), bitmap.wirevec(row[2]), bitmap.wirevec(row[3]), bitmap.wirevec(row[4]));
+            cells.push(ExportCell::new(
+                ty,
+                &[("WIDTH", y.len() as i64)],
+                vec![("A", false, a), ("B", false, b), ("S", false, s), ("Y", true, y)]
+            ));
+        }
+
+        // (kind, clk, en, rst, rst_val, d, q, t)
+        for row in rows.dffs.iter() {
+            let kind = self.reg_kinds.get_by_right(&row[0]).expect("Unknown register kind");
+            let &(_, clk_port, en_port, rst_port, rst_val_port) = Self::RTLIL_REG_TYPES.iter()
+                .find(|r| r.0 == kind.ty)
+                .expect("Unknown register type");
+            let (d, q) = (bitmap.wirevec(row[5]), bitmap.wirevec(row[6]));
+            let mut cell = ExportCell::new(&kind.ty, &[("WIDTH", q.len() as i64)], Vec::new());
+            for (name, polarity) in kind.polarities.iter() {
+                cell.params.insert(name.clone(), Self::param_to_json(*polarity as i64));
+            }
+            for (port, col) in [(clk_port, 1), (en_port, 2), (rst_port, 3), (rst_val_port, 4)] {
+                if port.is_empty() {
+                    continue;
+                }
+                let bits = bitmap.wirevec(row[col]);
+                match port.ends_with("_VALUE") {
+                    // reset values are parameters, MSB first
+                    true => {
+                        let value = ParamBits::from_bits(bits.iter().map(|b| match b {
+                            Bit::Const(c) => *c,
+                            Bit::Net(_) => 'x'
+                        }).collect());
+                        cell.params.insert(port.to_string(), value.to_json());
+                    },
+                    false => cell.conns.push((port.to_string(), false, bits))
+                }
+            }
+            cell.conns.push(("D".to_string(), false, d));
+            cell.conns.push(("Q".to_string(), true, q));
+            cells.push(cell);
+        }
+
+        // (sig, ins, outs, t)
+        for row in rows.opaque.iter() {
+            let sig = self.opaque_sigs.get_by_right(&row[0]).expect("Unknown opaque cell signature");
+            let mut conns = Vec::new();
+            for (ports, vecs, is_output) in [(&sig.inputs, row[1], false), (&sig.outputs, row[2], true)] {
+                for (port, vec) in ports.iter().zip(bitmap.wires(vecs)) {
+                    conns.push((port.clone(), is_output, bitmap.wirevec(vec)));
+                }
+            }
+            cells.push(ExportCell {
+                ty: sig.ty.clone(),
+                params: serde_json::from_str(&sig.params).expect("Malformed opaque cell parameters"),
+                conns
+            });
+        }
+
+        cells
+    }
+
+    pub(crate) fn param_to_json(value: i64) -> serde_json::Value {
+        ParamBits::from_i64(value, 32).to_json()
+    }
+
+    /// Keeps the first driver of every net and routes the outputs of later drivers to dangling nets.
+    pub(crate) fn unique_drivers(ports: &[ExportPort], cells: &mut [ExportCell], bitmap: &mut BitMap) {
+        let mut driven: HashSet<Bit> = ports.iter()
+            .filter(|p| p.direction == "input")
+            .flat_map(|p| p.bits.iter().copied())
+            .collect();
+        for (_, is_output, bits) in cells.iter_mut().flat_map(|c| c.conns.iter_mut()) {
+            if !*is_output {
+                continue;
+            }
+            for bit in bits.iter_mut() {
+                if matches!(bit, Bit::Const(_)) || !driven.insert(*bit) {
+                    *bit = bitmap.fresh();
+                }
+            }
+        }
+    }
+
+    /// Exports every row in the cell tables as a Yosys JSON netlist with a single module.
+    /// Rows made equivalent by rewrites drive the same nets, so all but the first of them get dangling outputs.
+    pub fn export_json(&self, top_mod: &str) -> serde_json::Value {
+        self.export_rows_json(top_mod, &self.all_rows())
+    }
+
+    /// Exports the given rows (e.g. an extraction result) as a Yosys JSON netlist with one driver per net.
+    pub fn export_rows_json(&self, top_mod: &str, rows: &CellRows) -> serde_json::Value {
+        let mut bitmap = BitMap::new(self);
+        let ports = self.lower_ports(&mut bitmap);
+        let mut cells = self.lower_cells(rows, &mut bitmap);
+        Self::unique_drivers(&ports, &mut cells, &mut bitmap);
+        let netnames = self.lower_netnames(&ports, &mut bitmap);
+        Self::module_json(top_mod, &ports, &cells, &netnames)
+    }
+
+    fn module_json(top_mod: &str, ports: &[ExportPort], cells: &[ExportCell], netnames: &[ExportNetName]) -> serde_json::Value {
+        let mut ports_json = Map::new();
+        let mut netnames_json = Map::new();
+        for port in ports.iter() {
+            let bits: Vec<_> = port.bits.iter().map(|b| b.to_json()).collect();
+            ports_json.insert(port.name.clone(), json!({
+                "direction": port.direction,
+                "bits": bits
+            }));
+            netnames_json.insert(port.name.clone(), json!({
+                "hide_name": 0,
+                "bits": bits,
+                "attributes": {}
+            }));
+        }
+        for netname in netnames.iter() {
+            let attributes = match &netname.src {
+                Some(src) => json!({ "src": src }),
+                None => json!({})
+            };
+            netnames_json.insert(netname.name.clone(), json!({
+                "hide_name": netname.hide_name as i64,
+                "bits": netname.bits.iter().map(|b| b.to_json()).collect::<Vec<_>>(),
+                "attributes": attributes
+            }));
+        }
+
+        let mut cells_json = Map::new();
+        for (i, cell) in cells.iter().enumerate() {
+            let mut directions = Map::new();
+            let mut conns = Map::new();
+            for (port, is_output, bits) in cell.conns.iter() {
+                directions.insert(port.clone(), json!(if *is_output { "output" } else { "input" }));
+                conns.insert(port.clone(), json!(bits.iter().map(|b| b.to_json()).collect::<Vec<_>>()));
+            }
+            cells_json.insert(format!("$eggrtl${}", i), json!({
+                "hide_name": 1,
+                "type": cell.ty,
+                "parameters": cell.params,
+                "attributes": {},
+                "port_directions": directions,
+                "connections": conns
+            }));
+        }
+
+        json!({
+            "creator": "eggrtl",
+            "modules": {
+                top_mod: {
+                    "attributes": {},
+                    "ports": ports_json,
+                    "cells": cells_json,
+                    "netnames": netnames_json
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,483 @@
+use core_relations::Value;
+use std::{
+    time::{Duration, Instant},
+    collections::{HashMap, HashSet}
+};
+use crate::core::{CellRows, NetlistDatabase};
+
+
+/// Assigns a cost to a single cell, given its internal type name (e.g. `$adds`, `$dff`)
+/// and the widths of its input and output ports. Costs must be positive.
+pub trait CostModel {
+    fn cell_cost(&self, ty: &str, in_widths: &[usize], out_width: usize) -> f64;
+}
+
+/// A rough area estimate: linear in width for bitwise and additive cells, quadratic for multipliers and dividers.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AreaCost;
+
+impl CostModel for AreaCost {
+    fn cell_cost(&self, ty: &str, in_widths: &[usize], out_width: usize) -> f64 {
+        let (ty, _) = NetlistDatabase::rtlil_type(ty);
+        let max_in = in_widths.iter().copied().max().unwrap_or(0);
+        match ty {
+            "$mul" | "$div" | "$mod" => (in_widths[0] * in_widths[1]).max(1) as f64,
+            "$add" | "$sub" => 2.0 * out_width.max(1) as f64,
+            "$eq" | "$ge" | "$le" | "$gt" | "$lt" | "$logic_and" | "$logic_or" | "$logic_not" |
+            "$reduce_and" | "$reduce_or" | "$reduce_xor" | "$reduce_xnor" | "$reduce_bool" => max_in.max(1) as f64,
+            _ => out_width.max(1) as f64
+        }
+    }
+}
+
+/// A set of rows chosen from the cell tables, one implementation per needed e-class.
+#[derive(Clone, Debug, Default)]
+pub struct Extraction {
+    pub rows: CellRows,
+    pub cost: f64
+}
+
+/// How `extract` chooses among equivalent rows.
+#[derive(Clone, Copy, Debug)]
+pub enum ExtractMode {
+    /// Bottom-up tree cost; fast, but shared logic is counted once per use.
+    Greedy,
+    /// Exact DAG cost (every selected row counted once) by branch and bound,
+    /// returning the best solution found so far when the time limit is hit.
+    Dag { time_limit: Duration }
+}
+
+/// The result of `extract`, with a lower bound on the optimal DAG cost.
+#[derive(Clone, Debug)]
+pub struct ExtractReport {
+    pub extraction: Extraction,
+    pub lower_bound: f64,
+    pub optimal: bool
+}
+
+impl ExtractReport {
+    /// Relative optimality gap of the extracted netlist, 0 when it is proven optimal.
+    pub fn gap(&self) -> f64 {
+        match self.extraction.cost > 0.0 {
+            true => ((self.extraction.cost - self.lower_bound) / self.extraction.cost).max(0.0),
+            false => 0.0
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum RowKind {
+    Ay,
+    Aby,
+    Absy,
+    Dff,
+    Opaque
+}
+
+/// A row of a cell table together with the canonical wires it reads and drives.
+pub(crate) struct Candidate {
+    pub(crate) kind: RowKind,
+    pub(crate) row: Vec<Value>,
+    pub(crate) inputs: Vec<Value>,
+    pub(crate) outputs: Vec<Value>,
+    pub(crate) cost: f64
+}
+
+impl Candidate {
+    /// Registers break combinational paths: their outputs are available before their inputs are driven.
+    pub(crate) fn is_register(&self) -> bool {
+        self.kind == RowKind::Dff
+    }
+}
+
+/// The extraction problem: candidate rows, wires that need no driver, and the wires that must be driven.
+/// Opaque cells are kept unconditionally (they may be blackboxes with side effects), so they are
+/// `forced`, their outputs are free and their inputs are roots.
+pub(crate) struct ExtractProblem {
+    pub(crate) candidates: Vec<Candidate>,
+    pub(crate) forced: Vec<usize>,
+    pub(crate) free: HashSet<Value>,
+    pub(crate) roots: Vec<Value>,
+    pub(crate) drivers: HashMap<Value, Vec<usize>>
+}
+
+impl ExtractProblem {
+    /// Collects the chosen candidates back into table rows.
+    pub(crate) fn to_extraction(&self, selected: &[usize]) -> Extraction {
+        let mut extraction = Extraction::default();
+        for &i in selected {
+            let cand = &self.candidates[i];
+            let rows = match cand.kind {
+                RowKind::Ay => &mut extraction.rows.ay,
+                RowKind::Aby => &mut extraction.rows.aby,
+                RowKind::Absy => &mut extraction.rows.absy,
+                RowKind::Dff => &mut extraction.rows.dffs,
+                RowKind::Opaque => &mut extraction.rows.opaque
+            };
+            rows.push(cand.row.clone());
+            extraction.cost += cand.cost;
+        }
+        extraction
+    }
+}
+
+impl NetlistDatabase {
+    pub(crate) fn extract_problem(&self, model: &dyn CostModel) -> ExtractProblem {
+        let canon = self.canon_map();
+        let wirevecs = self.wirevecs();
+        let canon_of = |v: Value| *canon.get(&v).unwrap_or(&v);
+        let wires_of = |vec: Value| -> Vec<Value> {
+            wirevecs.get(&vec)
+                .or_else(|| wirevecs.get(&canon_of(vec)))
+                .expect("Wirevec not found in container values")
+                .iter()
+                .map(|w| canon_of(*w))
+                .collect()
+        };
+
+        // (kind, table rows, input columns, output column)
+        let rows = self.all_rows();
+        let layouts: [(RowKind, &Vec<Vec<Value>>, &[usize], usize); 4] = [
+            (RowKind::Ay, &rows.ay, &[1], 2),
+            (RowKind::Aby, &rows.aby, &[1, 2], 3),
+            (RowKind::Absy, &rows.absy, &[1, 2, 3], 4),
+            (RowKind::Dff, &rows.dffs, &[1, 2, 3, 4, 5], 6)
+        ];
+
+        let mut candidates = Vec::new();
+        for (kind, rows, in_cols, out_col) in layouts {
+            for row in rows.iter() {
+                let ins: Vec<Vec<Value>> = in_cols.iter().map(|c| wires_of(row[*c])).collect();
+                let outputs = wires_of(row[out_col]);
+                let ty = match kind {
+                    RowKind::Dff => self.reg_kinds.get_by_right(&row[0]).expect("Unknown register kind").ty.as_str(),
+                    _ => self.types.get_by_right(&row[0]).expect("Unknown cell type").as_str()
+                };
+                let in_widths: Vec<usize> = ins.iter().map(|v| v.len()).collect();
+                let cost = model.cell_cost(ty, &in_widths, outputs.len()).max(1e-9);
+                let mut inputs: Vec<Value> = ins.into_iter().flatten().collect();
+                inputs.sort();
+                inputs.dedup();
+                candidates.push(Candidate { kind, row: row.clone(), inputs, outputs, cost });
+            }
+        }
+
+        // (sig, ins, outs, t)
+        let mut forced = Vec::new();
+        for row in rows.opaque.iter() {
+            let sig = self.opaque_sigs.get_by_right(&row[0]).expect("Unknown opaque cell signature");
+            let ins: Vec<Vec<Value>> = wires_of(row[1]).into_iter().map(&wires_of).collect();
+            let outputs: Vec<Value> = wires_of(row[2]).into_iter().flat_map(&wires_of).collect();
+            let in_widths: Vec<usize> = ins.iter().map(|v| v.len()).collect();
+            let cost = model.cell_cost(&sig.ty, &in_widths, outputs.len()).max(1e-9);
+            let mut inputs: Vec<Value> = ins.into_iter().flatten().collect();
+            inputs.sort();
+            inputs.dedup();
+            forced.push(candidates.len());
+            candidates.push(Candidate { kind: RowKind::Opaque, row: row.clone(), inputs, outputs, cost });
+        }
+
+        // constants and module inputs need no driver
+        let mut free = HashSet::new();
+        for wire in Self::CONST_BITS.iter().map(|(_, w)| w).chain(self.from_inputs.values()) {
+            if let Some(val) = self.wires.get_by_left(wire) {
+                free.insert(canon_of(*val));
+            }
+        }
+        let mut roots: Vec<Value> = self.as_outputs.values()
+            .filter_map(|w| self.wires.get_by_left(w))
+            .map(|v| canon_of(*v))
+            .collect();
+        for i in forced.iter() {
+            free.extend(candidates[*i].outputs.iter().copied());
+            roots.extend(candidates[*i].inputs.iter().copied());
+        }
+
+        let mut drivers: HashMap<Value, Vec<usize>> = HashMap::new();
+        for (i, cand) in candidates.iter().enumerate().filter(|(_, c)| c.kind != RowKind::Opaque) {
+            for out in cand.outputs.iter() {
+                let ds = drivers.entry(*out).or_default();
+                if ds.last() != Some(&i) {
+                    ds.push(i);
+                }
+            }
+        }
+
+        ExtractProblem { candidates, forced, free, roots, drivers }
+    }
+
+    /// Picks, for every wire, the candidate with the lowest tree cost, then walks back from the outputs.
+    pub(crate) fn extract_greedy_problem(problem: &ExtractProblem) -> Vec<usize> {
+        let candidates = &problem.candidates;
+
+        // bottom-up: relax tree costs until no wire finds a cheaper driver
+        let mut tree = vec![f64::INFINITY; candidates.len()];
+        let mut best: HashMap<Value, usize> = HashMap::new();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (i, cand) in candidates.iter().enumerate().filter(|(_, c)| c.kind != RowKind::Opaque) {
+                let mut cost = cand.cost;
+                if !cand.is_register() {
+                    let deps: Option<HashSet<usize>> = cand.inputs.iter()
+                        .filter(|w| !problem.free.contains(*w))
+                        .map(|w| best.get(w).copied())
+                        .collect();
+                    let Some(deps) = deps else {
+                        continue;
+                    };
+                    cost += deps.iter().map(|j| tree[*j]).sum::<f64>();
+                }
+                if cost >= tree[i] {
+                    continue;
+                }
+                tree[i] = cost;
+                changed = true;
+                for out in cand.outputs.iter() {
+                    if best.get(out).is_none_or(|j| *j == i || cost < tree[*j]) {
+                        best.insert(*out, i);
+                    }
+                }
+            }
+        }
+
+        // top-down: select the best driver of every needed wire
+        let mut selected = problem.forced.clone();
+        let mut chosen: HashSet<usize> = problem.forced.iter().copied().collect();
+        let mut driven: HashSet<Value> = problem.free.clone();
+        let mut needed = problem.roots.clone();
+        while let Some(wire) = needed.pop() {
+            if driven.contains(&wire) {
+                continue;
+            }
+            let Some(i) = best.get(&wire) else {
+                continue;   // undriven in the original netlist as well
+            };
+            if chosen.insert(*i) {
+                selected.push(*i);
+                driven.extend(candidates[*i].outputs.iter().copied());
+                needed.extend(candidates[*i].inputs.iter().copied());
+            }
+        }
+        selected
+    }
+
+    /// Greedily extracts one implementation per needed e-class, minimizing the tree cost under `model`.
+    pub fn extract_greedy(&self, model: &dyn CostModel) -> Extraction {
+        let problem = self.extract_problem(model);
+        let selected = Self::extract_greedy_problem(&problem);
+        problem.to_extraction(&selected)
+    }
+
+    /// Extracts a netlist with `mode`, reporting how far it may be from the optimal DAG cost.
+    pub fn extract(&self, model: &dyn CostModel, mode: ExtractMode) -> ExtractReport {
+        let problem = self.extract_problem(model);
+        let greedy = Self::extract_greedy_problem(&problem);
+        match mode {
+            ExtractMode::Greedy => {
+                let extraction = problem.to_extraction(&greedy);
+                let lower_bound = DagSearch::new(&problem, Instant::now()).root_bound();
+                ExtractReport { optimal: extraction.cost <= lower_bound, extraction, lower_bound }
+            },
+            ExtractMode::Dag { time_limit } => {
+                let mut search = DagSearch::new(&problem, Instant::now() + time_limit);
+                search.incumbent = problem.to_extraction(&greedy).cost;
+                search.best = greedy;
+                search.run();
+                let extraction = problem.to_extraction(&search.best);
+                let lower_bound = search.open_bound.min(extraction.cost);
+                ExtractReport { optimal: !search.timed_out, extraction, lower_bound }
+            }
+        }
+    }
+}
+
+/// Depth-first branch and bound over the choice of driver for each pending wire.
+/// The search stack is explicit since its depth grows with the number of selected rows.
+struct DagSearch<'a> {
+    problem: &'a ExtractProblem,
+    deadline: Instant,
+    min_cost: HashMap<Value, f64>,
+    selected: Vec<usize>,
+    in_selection: Vec<bool>,
+    cost: f64,
+    driven: HashMap<Value, usize>,
+    need: HashMap<Value, usize>,
+    best: Vec<usize>,
+    incumbent: f64,
+    open_bound: f64,
+    timed_out: bool
+}
+
+/// A branching point: the pending wire and the drivers still to try for it.
+struct Frame {
+    options: Vec<usize>,
+    next: usize,
+    applied: Option<usize>,
+    bound: f64
+}
+
+impl<'a> DagSearch<'a> {
+    fn new(problem: &'a ExtractProblem, deadline: Instant) -> Self {
+        let min_cost = problem.drivers.iter()
+            .map(|(w, ds)| (*w, ds.iter().map(|i| problem.candidates[*i].cost).fold(f64::INFINITY, f64::min)))
+            .collect();
+        let need = problem.roots.iter()
+            .filter(|w| !problem.free.contains(*w) && problem.drivers.contains_key(*w))
+            .map(|w| (*w, 1))
+            .collect();
+        let mut search = Self {
+            problem, deadline, min_cost,
+            selected: Vec::new(), in_selection: vec![false; problem.candidates.len()], cost: 0.0,
+            driven: HashMap::new(), need,
+            best: Vec::new(), incumbent: f64::INFINITY, open_bound: f64::INFINITY, timed_out: false
+        };
+        for i in problem.forced.iter() {
+            search.apply(*i);
+        }
+        search
+    }
+
+    fn apply(&mut self, i: usize) {
+        let cand = &self.problem.candidates[i];
+        self.selected.push(i);
+        self.in_selection[i] = true;
+        self.cost += cand.cost;
+        for out in cand.outputs.iter() {
+            *self.driven.entry(*out).or_default() += 1;
+        }
+        for input in cand.inputs.iter() {
+            if !self.problem.free.contains(input) && self.problem.drivers.contains_key(input) {
+                *self.need.entry(*input).or_default() += 1;
+            }
+        }
+    }
+
+    fn undo(&mut self, i: usize) {
+        let cand = &self.problem.candidates[i];
+        self.selected.pop();
+        self.in_selection[i] = false;
+        self.cost -= cand.cost;
+        for (map, wires) in [(&mut self.driven, &cand.outputs), (&mut self.need, &cand.inputs)] {
+            for w in wires.iter() {
+                if let Some(count) = map.get_mut(w) {
+                    *count -= 1;
+                    if *count == 0 {
+                        map.remove(w);
+                    }
+                }
+            }
+        }
+    }
+
+    fn pending(&self) -> Vec<Value> {
+        self.need.keys().filter(|w| !self.driven.contains_key(*w)).copied().collect()
+    }
+
+    /// Pending wires whose driver sets are pairwise disjoint each need a distinct row,
+    /// so the sum of their cheapest drivers is a valid lower bound on the remaining cost.
+    fn remaining_bound(&self, pending: &[Value]) -> f64 {
+        let mut wires: Vec<(f64, Value)> = pending.iter().map(|w| (self.min_cost[w], *w)).collect();
+        wires.sort_by(|a, b| b.0.total_cmp(&a.0));
+        let mut used = HashSet::new();
+        let mut bound = 0.0;
+        for (cost, w) in wires {
+            let ds = &self.problem.drivers[&w];
+            if ds.iter().all(|i| !used.contains(i)) {
+                bound += cost;
+                used.extend(ds.iter().copied());
+            }
+        }
+        bound
+    }
+
+    fn root_bound(&self) -> f64 {
+        self.cost + self.remaining_bound(&self.pending())
+    }
+
+    /// Selected combinational rows must not form a loop; registers break every path through them.
+    fn is_acyclic(&self) -> bool {
+        let candidates = &self.problem.candidates;
+        let comb: Vec<usize> = self.selected.iter().copied().filter(|i| !candidates[*i].is_register()).collect();
+        let mut indegree: HashMap<usize, usize> = comb.iter().map(|i| (*i, 0)).collect();
+        let mut users: HashMap<usize, Vec<usize>> = HashMap::new();
+        for &i in comb.iter() {
+            let mut deps: Vec<usize> = candidates[i].inputs.iter()
+                .flat_map(|w| self.problem.drivers.get(w).into_iter().flatten())
+                .copied()
+                .filter(|j| indegree.contains_key(j))
+                .collect();
+            deps.sort();
+            deps.dedup();
+            for j in deps {
+                users.entry(j).or_default().push(i);
+                *indegree.get_mut(&i).unwrap() += 1;
+            }
+        }
+        let mut ready: Vec<usize> = indegree.iter().filter(|(_, d)| **d == 0).map(|(i, _)| *i).collect();
+        let mut visited = 0;
+        while let Some(j) = ready.pop() {
+            visited += 1;
+            for i in users.get(&j).into_iter().flatten() {
+                let d = indegree.get_mut(i).unwrap();
+                *d -= 1;
+                if *d == 0 {
+                    ready.push(*i);
+                }
+            }
+        }
+        visited == comb.len()
+    }
+
+    /// Evaluates the current node, returning a frame to branch on if it can still beat the incumbent.
+    fn expand(&mut self) -> Option<Frame> {
+        let pending = self.pending();
+        if pending.is_empty() {
+            if self.cost < self.incumbent && self.is_acyclic() {
+                self.incumbent = self.cost;
+                self.best = self.selected.clone();
+            }
+            return None;
+        }
+        let bound = self.cost + self.remaining_bound(&pending);
+        if bound >= self.incumbent {
+            return None;
+        }
+
+        // branch on the most constrained wire, cheapest drivers first
+        let wire = pending.into_iter().min_by_key(|w| self.problem.drivers[w].len()).unwrap();
+        let candidates = &self.problem.candidates;
+        let mut options: Vec<usize> = self.problem.drivers[&wire].iter()
+            .copied()
+            .filter(|i| !self.in_selection[*i])
+            .filter(|i| candidates[*i].is_register() || candidates[*i].inputs.iter().all(|w| !candidates[*i].outputs.contains(w)))
+            .collect();
+        options.sort_by(|a, b| candidates[*a].cost.total_cmp(&candidates[*b].cost));
+        Some(Frame { options, next: 0, applied: None, bound })
+    }
+
+    fn run(&mut self) {
+        let mut stack: Vec<Frame> = self.expand().into_iter().collect();
+        while let Some(frame) = stack.last_mut() {
+            if let Some(i) = frame.applied.take() {
+                self.undo(i);
+            }
+            if Instant::now() >= self.deadline {
+                self.timed_out = true;
+                self.open_bound = stack.iter().map(|f| f.bound).fold(f64::INFINITY, f64::min);
+                return;
+            }
+            if frame.next >= frame.options.len() || frame.bound >= self.incumbent {
+                stack.pop();
+                continue;
+            }
+            let i = frame.options[frame.next];
+            frame.next += 1;
+            frame.applied = Some(i);
+            self.apply(i);
+            if let Some(child) = self.expand() {
+                stack.push(child);
+            }
+        }
+    }
+}
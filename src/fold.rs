@@ -0,0 +1,241 @@
+use core_relations::{PlanStrategy, Value};
+use std::ops::Range;
+use crate::core::NetlistDatabase;
+use crate::rewrites::{Term::{Const, Var}, add_seminaive_rule, atom};
+
+
+// auxiliary functions
+fn extend(value: u64, width: usize, signed: bool) -> i128 {
+    match signed && width > 0 && (value >> (width - 1)) & 1 == 1 {
+        true => value as i128 - (1i128 << width),
+        false => value as i128
+    }
+}
+
+fn mask(width: usize) -> u128 {
+    match width {
+        w if w >= 128 => u128::MAX,
+        w => (1u128 << w) - 1
+    }
+}
+
+/// Evaluates a cell of internal type `ty` on constant operands given as (value, width),
+/// following the RTLIL rules for extending operands by signedness. Returns the low `width`
+/// bits of the result, or `None` if the result is undefined (e.g. division by zero) or unsupported.
+pub(crate) fn fold_cell(ty: &str, ins: &[(u64, usize)], width: usize) -> Option<u64> {
+    if width > 64 || ins.iter().any(|(_, w)| *w > 64) {
+        return None;
+    }
+    let (op, signed) = NetlistDatabase::rtlil_type(ty);
+    let a = ins.first().map(|(v, w)| extend(*v, *w, signed));
+    let b = ins.get(1).map(|(v, w)| extend(*v, *w, signed));
+    let (a_raw, a_width) = *ins.first()?;
+    let shift = || ins.get(1).map(|(v, _)| (*v).min(127) as u32);  // shift amounts are unsigned
+
+    let result: i128 = match (op, a, b) {
+        ("$not", Some(a), None) => !a,
+        ("$neg", Some(a), None) => a.wrapping_neg(),
+        ("$pos", Some(a), None) => a,
+        ("$logic_not", Some(a), None) => (a == 0) as i128,
+        ("$reduce_and", Some(_), None) => (a_width > 0 && a_raw as u128 == mask(a_width)) as i128,
+        ("$reduce_or" | "$reduce_bool", Some(a), None) => (a != 0) as i128,
+        ("$reduce_xor", Some(_), None) => (a_raw.count_ones() % 2) as i128,
+        ("$reduce_xnor", Some(_), None) => (a_raw.count_ones() % 2 == 0) as i128,
+
+        ("$and", Some(a), Some(b)) => a & b,
+        ("$or", Some(a), Some(b)) => a | b,
+        ("$xor", Some(a), Some(b)) => a ^ b,
+        ("$nand", Some(a), Some(b)) => !(a & b),
+        ("$nor", Some(a), Some(b)) => !(a | b),
+        ("$xnor", Some(a), Some(b)) => !(a ^ b),
+        ("$eq", Some(a), Some(b)) => (a == b) as i128,
+        ("$ge", Some(a), Some(b)) => (a >= b) as i128,
+        ("$le", Some(a), Some(b)) => (a <= b) as i128,
+        ("$gt", Some(a), Some(b)) => (a > b) as i128,
+        ("$lt", Some(a), Some(b)) => (a < b) as i128,
+        ("$logic_and", Some(a), Some(b)) => (a != 0 && b != 0) as i128,
+        ("$logic_or", Some(a), Some(b)) => (a != 0 || b != 0) as i128,
+
+        ("$add", Some(a), Some(b)) => a.wrapping_add(b),
+        ("$sub", Some(a), Some(b)) => a.wrapping_sub(b),
+        ("$mul", Some(a), Some(b)) => a.wrapping_mul(b),
+        ("$div", Some(a), Some(b)) => a.checked_div(b)?,    // truncates towards zero, like Verilog
+        ("$mod", Some(a), Some(b)) => a.checked_rem(b)?,    // takes the sign of a, like Verilog
+
+        // A is extended to the wider of A and Y before shifting
+        ("$shl" | "$sshl", Some(a), Some(_)) => a.checked_shl(shift()?).unwrap_or(0),
+        ("$shr", Some(a), Some(_)) => ((a as u128 & mask(a_width.max(width))) >> shift()?) as i128,
+        ("$sshr", Some(a), Some(_)) => match signed {
+            true => a >> shift()?,
+            false => ((a as u128 & mask(a_width.max(width))) >> shift()?) as i128
+        },
+        _ => return None
+    };
+    Some((result as u128 & mask(width)) as u64)
+}
+
+impl NetlistDatabase {
+    pub fn rewrite_fold_all(&mut self, strategy: PlanStrategy, recent_range: Range<Value>) -> bool {
+        let funcs = &self.funcs;
+        let (ay, aby, absy, const_words) = (self.ay_cells, self.aby_cells, self.absy_cells, self.const_words);
+        let ty = |name: &str| *self.types.get_by_left(name).unwrap();
+        let word = |value: i64| self.db.base_values().get::<i64>(value);
+        let (zero, one) = (word(0), word(1));
+
+        let mut rsb = self.db.new_rule_set();
+
+        // ay_fold and aby_fold
+        // op(k1, k2) => k, if every input is a constant
+        for ay_type in Self::AY_TYPES {
+            let t = ty(ay_type);
+            add_seminaive_rule(&mut rsb, strategy, &recent_range, &[
+                atom(ay, &[Const(t), Var("a"), Var("y")])
+            ], |rhs, v| {
+                let k = rhs.call_external(funcs.fold, &[t.into(), v["a"].into(), v["y"].into()]).unwrap();
+                rhs.call_external(funcs.union_vecs, &[k.into(), v["y"].into()]).unwrap();
+            });
+        }
+        for aby_type in Self::ABY_TYPES {
+            let t = ty(aby_type);
+            add_seminaive_rule(&mut rsb, strategy, &recent_range, &[
+                atom(aby, &[Const(t), Var("a"), Var("b"), Var("y")])
+            ], |rhs, v| {
+                let k = rhs.call_external(funcs.fold, &[t.into(), v["a"].into(), v["b"].into(), v["y"].into()]).unwrap();
+                rhs.call_external(funcs.union_vecs, &[k.into(), v["y"].into()]).unwrap();
+            });
+        }
+
+        // absy_mux_const
+        // mux(a, b, 0) => a, mux(a, b, 1) => b
+        let mux = ty("$mux");
+        for (sel, picked) in [(zero, "a"), (one, "b")] {
+            add_seminaive_rule(&mut rsb, strategy, &recent_range, &[
+                atom(absy, &[Const(mux), Var("a"), Var("b"), Var("s"), Var("y")]),
+                atom(const_words, &[Var("s"), Const(sel)])
+            ], |rhs, v| {
+                rhs.call_external(funcs.union_vecs, &[v[picked].into(), v["y"].into()]).unwrap();
+            });
+        }
+
+        // absy_mux_same
+        // mux(a, a, s) => a
+        add_seminaive_rule(&mut rsb, strategy, &recent_range, &[
+            atom(absy, &[Const(mux), Var("a"), Var("a"), Var("s"), Var("y")])
+        ], |rhs, v| {
+            rhs.call_external(funcs.union_vecs, &[v["a"].into(), v["y"].into()]).unwrap();
+        });
+
+        // aby_identity
+        // a + 0 => a, a - 0 => a, a | 0 => a, a ^ 0 => a, a * 1 => a, if a is as wide as the result
+        // (union_vecs checks the widths, so a is never extended or truncated)
+        let identities = [
+            ("$adds", funcs.is_zero, true), ("$addu", funcs.is_zero, true),
+            ("$subs", funcs.is_zero, false), ("$subu", funcs.is_zero, false),
+            ("$ors", funcs.is_zero, true), ("$oru", funcs.is_zero, true), ("$xors", funcs.is_zero, true), ("$xoru", funcs.is_zero, true),
+            ("$muls", funcs.is_one_s, true), ("$mulu", funcs.is_one_u, true)
+        ];
+        for (id_type, is_identity, commutes) in identities {
+            let t = ty(id_type);
+            let sides: &[(&str, &str)] = if commutes { &[("a", "b"), ("b", "a")] } else { &[("a", "b")] };
+            for (kept, neutral) in sides.iter().copied() {
+                add_seminaive_rule(&mut rsb, strategy, &recent_range, &[
+                    atom(aby, &[Const(t), Var("a"), Var("b"), Var("y")])
+                ], |rhs, v| {
+                    rhs.call_external(is_identity, &[v[neutral].into()]).unwrap();
+                    rhs.call_external(funcs.union_vecs, &[v[kept].into(), v["y"].into()]).unwrap();
+                });
+            }
+        }
+
+        // aby_annihilate
+        // a & 0 => 0, a * 0 => 0
+        for zero_type in ["$ands", "$andu", "$muls", "$mulu"] {
+            let t = ty(zero_type);
+            for operand in ["a", "b"] {
+                add_seminaive_rule(&mut rsb, strategy, &recent_range, &[
+                    atom(aby, &[Const(t), Var("a"), Var("b"), Var("y")])
+                ], |rhs, v| {
+                    rhs.call_external(funcs.is_zero, &[v[operand].into()]).unwrap();
+                    let k = rhs.call_external(funcs.zero_like, &[v["y"].into()]).unwrap();
+                    rhs.call_external(funcs.union_vecs, &[k.into(), v["y"].into()]).unwrap();
+                });
+            }
+        }
+
+        // finish and run the rule set
+        let rs = rsb.build();
+        self.db.run_rule_set(&rs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fold_cell;
+
+    #[test]
+    fn extends_operands_by_signedness() {
+        // -1 + 1 against 15 + 1, on a wider result
+        assert_eq!(fold_cell("$adds", &[(0b1111, 4), (0b01, 2)], 8), Some(0));
+        assert_eq!(fold_cell("$addu", &[(0b1111, 4), (0b01, 2)], 8), Some(16));
+        // -2 * 3 against 14 * 3
+        assert_eq!(fold_cell("$muls", &[(0b1110, 4), (0b0011, 4)], 8), Some(0xfa));
+        assert_eq!(fold_cell("$mulu", &[(0b1110, 4), (0b0011, 4)], 8), Some(42));
+        assert_eq!(fold_cell("$nots", &[(0b10, 2)], 4), Some(0b0001));
+        assert_eq!(fold_cell("$notu", &[(0b10, 2)], 4), Some(0b1101));
+        assert_eq!(fold_cell("$lts", &[(0b1111, 4), (0b0001, 4)], 1), Some(1));
+        assert_eq!(fold_cell("$ltu", &[(0b1111, 4), (0b0001, 4)], 1), Some(0));
+    }
+
+    #[test]
+    fn truncates_to_the_result_width() {
+        assert_eq!(fold_cell("$addu", &[(15, 4), (1, 4)], 4), Some(0));
+        assert_eq!(fold_cell("$subu", &[(0, 4), (1, 4)], 4), Some(0b1111));
+        assert_eq!(fold_cell("$mulu", &[(u64::MAX, 64), (u64::MAX, 64)], 64), Some(1));
+    }
+
+    #[test]
+    fn divides_like_verilog() {
+        // -7 / 2 and -7 % 2 truncate towards zero, against 9 / 2 and 9 % 2
+        assert_eq!(fold_cell("$divs", &[(0b1001, 4), (2, 4)], 4), Some(0b1101));
+        assert_eq!(fold_cell("$mods", &[(0b1001, 4), (2, 4)], 4), Some(0b1111));
+        assert_eq!(fold_cell("$divu", &[(0b1001, 4), (2, 4)], 4), Some(4));
+        assert_eq!(fold_cell("$modu", &[(0b1001, 4), (2, 4)], 4), Some(1));
+        // the most negative value divided by -1 does not overflow
+        assert_eq!(fold_cell("$divs", &[(1 << 63, 64), (u64::MAX, 64)], 64), Some(1 << 63));
+    }
+
+    #[test]
+    fn leaves_division_by_zero_unfolded() {
+        for ty in ["$divs", "$divu", "$mods", "$modu"] {
+            assert_eq!(fold_cell(ty, &[(5, 4), (0, 4)], 4), None, "{}", ty);
+        }
+    }
+
+    #[test]
+    fn shifts_by_64_bits_or_more() {
+        for amount in [64, 65, 127, 200, 255] {
+            assert_eq!(fold_cell("$shlu", &[(1, 8), (amount, 8)], 8), Some(0), "{}", amount);
+            assert_eq!(fold_cell("$shru", &[(0xff, 8), (amount, 8)], 8), Some(0), "{}", amount);
+            assert_eq!(fold_cell("$sshru", &[(0x80, 8), (amount, 8)], 8), Some(0), "{}", amount);
+            assert_eq!(fold_cell("$sshrs", &[(0x80, 8), (amount, 8)], 8), Some(0xff), "{}", amount);
+            assert_eq!(fold_cell("$sshrs", &[(0x40, 8), (amount, 8)], 8), Some(0), "{}", amount);
+        }
+        assert_eq!(fold_cell("$shlu", &[(1, 64), (63, 8)], 64), Some(1 << 63));
+    }
+
+    #[test]
+    fn shifts_a_extended_to_the_result_width() {
+        assert_eq!(fold_cell("$shlu", &[(1, 1), (3, 2)], 8), Some(8));
+        // $shr shifts in zeros, after sign-extending a signed A
+        assert_eq!(fold_cell("$shrs", &[(0b1000, 4), (1, 2)], 8), Some(0x7c));
+        assert_eq!(fold_cell("$shru", &[(0b1000, 4), (1, 2)], 8), Some(0b0100));
+        assert_eq!(fold_cell("$sshrs", &[(0b1000, 4), (1, 2)], 8), Some(0xfc));
+    }
+
+    #[test]
+    fn leaves_wide_and_unknown_cells_unfolded() {
+        assert_eq!(fold_cell("$addu", &[(1, 65), (1, 1)], 8), None);
+        assert_eq!(fold_cell("$addu", &[(1, 8), (1, 8)], 65), None);
+        assert_eq!(fold_cell("$shiftxu", &[(1, 8), (1, 8)], 8), None);
+    }
+}
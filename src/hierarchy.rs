@@ -0,0 +1,162 @@
+use std::collections::{BTreeMap, HashMap};
+use crate::core::NetlistDatabase;
+use crate::error::{Location, NetlistError};
+use crate::yosys::{self, JsonBit};
+
+
+/// How the importer treats instances of other modules of the same netlist.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Hierarchy {
+    /// Inline every instance into the tables of the top module, with fresh wire ids for its internal nets.
+    #[default]
+    Flatten,
+    /// Keep instances as opaque cells; `build_modules_from_json` imports each module into its own database.
+    Keep
+}
+
+/// Cells and netnames of inlined instances, named by their hierarchical path and rewired to the top module.
+#[derive(Default)]
+pub(crate) struct Flattened {
+    pub(crate) cells: Vec<(String, yosys::Cell)>,
+    pub(crate) netnames: Vec<(String, yosys::Netname)>
+}
+
+// auxiliary functions
+fn remap_bits(bits: &mut [JsonBit], map: &mut HashMap<i64, JsonBit>, next_id: &mut i64) {
+    for bit in bits.iter_mut() {
+        // constants are shared by every module
+        if let JsonBit::Net(id) = *bit {
+            *bit = *map.entry(id).or_insert_with(|| {
+                // a net internal to the instance
+                *next_id += 1;
+                JsonBit::Net(*next_id - 1)
+            });
+        }
+    }
+}
+
+/// A `$pos` cell driving the parent bits `y` from `a`.
+fn buffer(a: Vec<JsonBit>, y: Vec<JsonBit>) -> yosys::Cell {
+    let width = NetlistDatabase::param_to_json(a.len() as i64);
+    let parameters = [("A_SIGNED", NetlistDatabase::param_to_json(0)), ("A_WIDTH", width.clone()), ("Y_WIDTH", width)];
+    yosys::Cell {
+        hide_name: Some(1),
+        ty: "$pos".to_string(),
+        parameters: parameters.into_iter().map(|(name, value)| (name.to_string(), value)).collect(),
+        attributes: yosys::Params::new(),
+        port_directions: None,
+        connections: BTreeMap::from([("A".to_string(), a), ("Y".to_string(), y)])
+    }
+}
+
+impl NetlistDatabase {
+    /// Whether `module` is a blackbox, a module with ports but no body to inline or import.
+    pub(crate) fn is_blackbox(module: &yosys::Module, loc: &Location) -> Result<bool, NetlistError> {
+        match module.attributes.get("blackbox") {
+            Some(value) => Ok(Self::param_to_i64(value, "blackbox", loc)? != 0),
+            None => Ok(false)
+        }
+    }
+
+    /// `cell`, an instance of `module` kept as an opaque cell, with the port directions of `module`
+    /// unless it already has its own.
+    pub(crate) fn with_port_directions(cell: &yosys::Cell, module: &yosys::Module) -> yosys::Cell {
+        let mut cell = cell.clone();
+        if cell.port_directions.is_none() {
+            cell.port_directions = Some(module.ports.iter().map(|(p, port)| (p.clone(), port.direction.clone())).collect());
+        }
+        cell
+    }
+
+    /// Inlines the instance `cell` (connected in the ids of the top module) of a module of `modules`,
+    /// appending its cells and netnames to `out` and recursing into the instances it contains.
+    /// `stack` holds the modules being inlined, so a module instantiating itself is an error.
+    pub(crate) fn flatten_instance(
+        modules: &BTreeMap<String, yosys::Module>, path: &str, cell: &yosys::Cell, loc: &Location,
+        next_id: &mut i64, stack: &mut Vec<String>, out: &mut Flattened
+    ) -> Result<(), NetlistError> {
+        if stack.contains(&cell.ty) {
+            return Err(NetlistError::RecursiveModule { loc: loc.clone(), module: cell.ty.clone() });
+        }
+        let module = &modules[&cell.ty];
+
+        // port bits of the instance are the connected bits of the parent, unconnected ports get fresh nets.
+        // Inputs go first, so an output bit that is an input, another output or a constant inside the
+        // instance gets a buffer from there to the parent bit it is connected to.
+        let mut map = HashMap::new();
+        let (mut buf_a, mut buf_y) = (Vec::new(), Vec::new());
+        let mut ports: Vec<(&String, &yosys::Port)> = module.ports.iter().collect();
+        ports.sort_by_key(|(_, port)| port.direction != "input");
+        for (name, port) in ports {
+            let Some(conn) = cell.connections.get(name) else {
+                continue;
+            };
+            if conn.len() != port.bits.len() {
+                return Err(NetlistError::PortWidth { loc: loc.clone(), port: name.clone(), expected: port.bits.len(), found: conn.len() });
+            }
+            for (bit, parent_bit) in port.bits.iter().zip(conn.iter()) {
+                let driver = match *bit {
+                    JsonBit::Net(id) => *map.entry(id).or_insert(*parent_bit),
+                    JsonBit::Const(_) => *bit
+                };
+                if port.direction != "input" && driver != *parent_bit && parent_bit.net().is_some() {
+                    buf_a.push(driver);
+                    buf_y.push(*parent_bit);
+                }
+            }
+        }
+        if !buf_a.is_empty() {
+            out.cells.push((format!("{}.$buf", path), buffer(buf_a, buf_y)));
+        }
+
+        stack.push(cell.ty.clone());
+        for (name, sub_cell) in module.cells.iter() {
+            let mut sub_cell = sub_cell.clone();
+            for bits in sub_cell.connections.values_mut() {
+                remap_bits(bits, &mut map, next_id);
+            }
+            let sub_path = format!("{}.{}", path, name);
+            match modules.get(&sub_cell.ty) {
+                Some(sub_mod) if Self::is_blackbox(sub_mod, &Location::module(&sub_cell.ty))? => {
+                    out.cells.push((sub_path, Self::with_port_directions(&sub_cell, sub_mod)));
+                },
+                Some(_) => Self::flatten_instance(modules, &sub_path, &sub_cell, loc, next_id, stack, out)?,
+                None => out.cells.push((sub_path, sub_cell))
+            }
+        }
+        for (name, netname) in module.netnames.iter() {
+            let mut netname = netname.clone();
+            remap_bits(&mut netname.bits, &mut map, next_id);
+            out.netnames.push((format!("{}.{}", path, name), netname));
+        }
+        stack.pop();
+        Ok(())
+    }
+
+    /// Imports `top_mod` and every module it instantiates, directly or not, into a database each.
+    /// Instances stay opaque cells of their module's type, and blackboxes get no database of their own.
+    /// Clock ports are the named inputs a module has.
+    pub fn build_modules_from_json(json_path: &str, top_mod: &str, clk_names: &[&str]) -> Result<Vec<(String, NetlistDatabase)>, NetlistError> {
+        let modules = yosys::Netlist::load(json_path)?.modules;
+
+        let mut todo = vec![top_mod.to_string()];
+        let mut dbs: Vec<(String, NetlistDatabase)> = Vec::new();
+        while let Some(mod_name) = todo.pop() {
+            if dbs.iter().any(|(m, _)| *m == mod_name) {
+                continue;
+            }
+            let module = modules.get(&mod_name).ok_or_else(|| NetlistError::MissingModule { module: mod_name.clone() })?;
+            let mod_clks: Vec<&str> = clk_names.iter().copied().filter(|c| module.ports.get(*c).is_some_and(|p| p.direction == "input")).collect();
+            let mut db = NetlistDatabase::default();
+            db.build_mod(&mod_name, &modules, &mod_clks, Hierarchy::Keep)?;
+            for cell in module.cells.values() {
+                match modules.get(&cell.ty) {
+                    Some(module) if !Self::is_blackbox(module, &Location::module(&cell.ty))? => todo.push(cell.ty.clone()),
+                    _ => {}
+                }
+            }
+            dbs.push((mod_name, db));
+        }
+        Ok(dbs)
+    }
+}
@@ -1,2 +1,16 @@
 pub mod core;
-pub mod rewrites;
\ No newline at end of file
+pub mod yosys;
+pub mod stream;
+pub mod batch;
+pub mod hierarchy;
+pub mod error;
+pub mod param;
+pub mod progress;
+pub mod rewrites;
+pub mod fold;
+pub mod rules;
+pub mod saturate;
+pub mod export;
+pub mod verilog;
+pub mod egglog;
+pub mod extract;
\ No newline at end of file
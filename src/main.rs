@@ -1,168 +1,320 @@
-use core_relations::{PlanStrategy, Value};
-use numeric_id::NumericId;
-use eggrtl::core::NetlistDatabase;
-
-fn main() {
-    println!("Hello, EggRTL!");
-    let mut netlist = NetlistDatabase::default();
-    netlist.build_from_json("dot_product.json", "top", "clk");
-    netlist.merge_all();
-    netlist.print_tables();
-
-    netlist.rewrite_basic_all(PlanStrategy::PureSize, Value::new(0)..Value::new(1));
-
-    netlist.merge_all();
-    netlist.print_tables();
-
-    serde_json::to_writer_pretty(std::fs::File::create("dot_product_out.json").unwrap(), &netlist.dump_tables()).unwrap();
-    // netlist.print_tables();
-
-    // let mut db = Database::default();
-    // let id_counter = db.add_counter();  // shared by both base and container values
-    // let ts_counter = db.add_counter();  // used for timestamps
-    // let displaced: core_relations::TableId = db.add_table(DisplacedTable::default(), iter::empty(), iter::empty()); // union-find structure
-
-    // db.base_values_mut().register_type::<i64>();    // register i64
-    // db.base_values_mut().register_type::<&'static str>(); // register str
-    // db.container_values_mut().register_type::<VecContainer>(id_counter, move |state, old, new| {
-    //     if old != new {
-    //         let next_ts = Value::from_usize(state.read_counter(ts_counter));
-    //         state.stage_insert(displaced, &[old, new, next_ts]);
-    //         std::cmp::min(old, new) // TODO: use a better way to determine the "old" value
-    //     }
-    //     else {
-    //         old
-    //     }
-    // });
-
-    // let wires_impl = SortedWritesTable::new(
-    //     1, 3, Some(ColumnId::new(2)), Vec::new(),
-    //     Box::new(move |state, expr1, expr2, res| {
-    //         if expr1 != expr2 {
-    //             state.stage_insert(displaced, &[expr1[1], expr2[1], expr2[2]]);
-    //             res.extend_from_slice(expr2);   // expr2 wins
-    //             true
-    //         }
-    //         else {
-    //             false
-    //         }
-    //     })
-    // );
-    // let wires = db.add_table(wires_impl, iter::once(displaced), iter::once(displaced));
-
-    // // (type, a, b, y, t)
-    // let aby_cells_impl = SortedWritesTable::new(
-    //     3, 5, Some(ColumnId::new(4)), Vec::new(),
-    //     Box::new(move |state, expr1, expr2, res| {
-    //         if expr1 != expr2 {
-    //             let vec1 = &state.container_values().get_val::<VecContainer>(expr1[3]).unwrap().0;
-    //             let vec2 = &state.container_values().get_val::<VecContainer>(expr2[3]).unwrap().0;
-    //             assert_eq!(vec1.len(), vec2.len());
-    //             for (elem1, elem2) in vec1.iter().zip(vec2.iter()) {    // union each pair of elements
-    //                 if elem1 != elem2 { 
-    //                     state.stage_insert(displaced, &[*elem1, *elem2, expr2[4]]);
-    //                 }
-    //             }
-    //             // state.stage_insert(displaced, &[expr1[3], expr2[3], expr2[4]]); // union the vecs
-    //             res.extend_from_slice(expr2);   // expr2 wins
-    //             true
-    //         }
-    //         else {
-    //             false
-    //         }
-    //     })
-    // );
-    // let aby_cells = db.add_table(aby_cells_impl, iter::once(displaced), iter::once(displaced));
-
-    // let ts = Value::from_usize(db.inc_counter(ts_counter));
-    // // add wires from 0 to 20
-    // let mut ws = Vec::new();
-    // {
-    //     let mut buf = db.get_table(wires).new_buffer();
-    //     for i in 0..20 {
-    //         let i = db.base_values().get(i as i64);
-    //         buf.stage_insert(&[i, Value::from_usize(db.inc_counter(id_counter)), ts]);
-    //         ws.push(i);
-    //     }
-    // }   // flush the buffer
-    // db.merge_all();
-
-    // // add types
-    // let mut types = HashMap::new();
-    // {
-    //     types.insert("$add", Value::from_usize(db.inc_counter(id_counter)));
-    //     types.insert("$mul", Value::from_usize(db.inc_counter(id_counter)));
-    //     types.insert("$sub", Value::from_usize(db.inc_counter(id_counter)));
-    // }
-    // println!("Types: {:?}", types);
-
-    // // add cells
-    // {
-    //     let mut buf = db.get_table(aby_cells).new_buffer();
-    //     let vec01 = db.with_execution_state(|state| {
-    //         state.container_values().register_val(VecContainer(vec![ws[0], ws[1]]), state)
-    //     });
-    //     let vec23 = db.with_execution_state(|state| {
-    //         state.container_values().register_val(VecContainer(vec![ws[2], ws[3]]), state)
-    //     });
-    //     let vec45 = db.with_execution_state(|state| {
-    //         state.container_values().register_val(VecContainer(vec![ws[4], ws[5]]), state)
-    //     });
-    //     let vec67 = db.with_execution_state(|state| {
-    //         state.container_values().register_val(VecContainer(vec![ws[6], ws[7]]), state)
-    //     });
-    //     let vec89 = db.with_execution_state(|state| {
-    //         state.container_values().register_val(VecContainer(vec![ws[8], ws[9]]), state)
-    //     });
-    //     let vec23_copy = db.with_execution_state(|state| {
-    //         state.container_values().register_val(VecContainer(vec![ws[2], ws[3]]), state)
-    //     });
-
-    //     println!("{:?}, {:?}, {:?}, {:?}, {:?}, {:?}", vec01, vec23, vec45, vec67, vec89, vec23_copy);
-
-    //     buf.stage_insert(&[types["$add"], vec01, vec23, vec45, ts]);
-    //     buf.stage_insert(&[types["$add"], vec23, vec01, vec45, ts]);
-    //     buf.stage_insert(&[types["$add"], vec01, vec23, vec67, ts]);
-    //     buf.stage_insert(&[types["$mul"], vec01, vec45, vec89, ts]);
-    //     buf.stage_insert(&[types["$mul"], vec01, vec67, vec89, ts]);
-    // }   // flush the buffer
-    // db.merge_all();
-
-    // println!("After merge:");
-    // dump_tables(&db, &[displaced, aby_cells]);
-
-    // // rebuild containers
-    // loop {
-    //     println!("Rebuilding...");
-    //     let container_modified = db.rebuild_containers(displaced);
-    //     let table_modified = db.apply_rebuild(displaced, &[wires, aby_cells], Value::from_usize(db.inc_counter(ts_counter)));
-    //     if !container_modified && !table_modified {
-    //         break;  // no more changes
-    //     }
-    // }
-
-    // println!("After rebuild containers:");
-    // dump_tables(&db, &[displaced, aby_cells]);
-
-    // let mut rsb = db.new_rule_set();
-    // {
-    //     let mut lhs = rsb.new_rule();
-    //     let ty = lhs.new_var();
-    //     let a = lhs.new_var();
-    //     let b = lhs.new_var();
-    //     let y = lhs.new_var();
-    //     let t = lhs.new_var();
-    //     lhs.add_atom(aby_cells, &[ty.into(), a.into(), b.into(), y.into(), t.into()], &[]).unwrap();
-    //     let mut rhs = lhs.build();
-    //     let id_canons = rhs.lookup_with_default(displaced, &[b.into()], b.into(), ColumnId::new(1)).unwrap();
-    //     rhs.assert_ne(b.into(), id_canons.into()).unwrap();
-    //     rhs.insert(aby_cells, &[ty.into(), a.into(), id_canons.into(), y.into(), t.into()]).unwrap();
-    //     rhs.remove(aby_cells, &[ty.into(), a.into(), b.into()]).unwrap();
-    //     rhs.build();
-    // }
-    // let rs = rsb.build();
-    // netlist.db.run_rule_set(&rs);
-
-    // println!("After rebuild tables:");
-    // dump_tables(&db, &[displaced, aby_cells]);
-}
\ No newline at end of file
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use eggrtl::core::{NetlistDatabase, NetlistStats};
+use eggrtl::error::{NetlistError, RuleError, VerilogError};
+use eggrtl::extract::{AreaCost, ExtractMode};
+use eggrtl::hierarchy::Hierarchy;
+use eggrtl::progress::{Progress, ProgressSink};
+use eggrtl::rules::RuleFile;
+use eggrtl::saturate::{RuleSet, RunConfig};
+use log::info;
+use std::{fmt, process::ExitCode, time::Duration};
+
+
+/// Equality saturation over Yosys JSON netlists.
+#[derive(Parser)]
+#[command(name = "eggrtl", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+    /// Log more (-v for info, -vv for debug, -vvv for trace); `RUST_LOG` overrides this.
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+    /// Write progress events to standard error as JSON lines.
+    #[arg(long, global = true)]
+    progress: bool
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Saturate a netlist with rewrite rules and write the cheapest equivalent netlist.
+    Optimize {
+        #[command(flatten)]
+        input: InputArgs,
+        /// Built-in rule sets (basic, shift, unary, register, fold) or `.rules` files; all built-in sets by default.
+        #[arg(long, value_delimiter = ',')]
+        rules: Vec<String>,
+        /// Maximum number of saturation iterations.
+        #[arg(long, default_value_t = 10)]
+        iters: usize,
+        /// Time limit of saturation, and separately of extraction, e.g. `60s`, `500ms` or `2m`.
+        #[arg(long, default_value = "60s", value_parser = parse_duration)]
+        timeout: Duration,
+        /// Stop saturating once the cell tables hold this many rows.
+        #[arg(long, default_value_t = 1_000_000)]
+        nodes: usize,
+        /// How to pick among equivalent cells.
+        #[arg(long, value_enum, default_value_t = Extractor::Dag)]
+        extract: Extractor,
+        #[command(flatten)]
+        output: OutputArgs
+    },
+    /// Import a netlist and print the sizes of its tables.
+    Stats {
+        #[command(flatten)]
+        input: InputArgs
+    },
+    /// Import a netlist and write it back without optimizing it.
+    Export {
+        #[command(flatten)]
+        input: InputArgs,
+        #[command(flatten)]
+        output: OutputArgs
+    }
+}
+
+#[derive(Args)]
+struct InputArgs {
+    /// Yosys JSON netlist, as written by `write_json`.
+    input: String,
+    /// Module to import.
+    #[arg(long, default_value = "top")]
+    top: String,
+    /// Clock input ports; clocks are inferred from the registers when none are given.
+    #[arg(long = "clock", value_delimiter = ',')]
+    clocks: Vec<String>,
+    /// Inline instances of other modules, or keep them as opaque cells and import each module on its own.
+    #[arg(long, value_enum, default_value_t = HierarchyArg::Flatten)]
+    hierarchy: HierarchyArg
+}
+
+#[derive(Args)]
+struct OutputArgs {
+    /// Output file; standard output if omitted.
+    #[arg(short, long)]
+    output: Option<String>,
+    /// Output format; guessed from the output file extension (`.v`, `.egg`) if omitted, JSON otherwise.
+    #[arg(long, value_enum)]
+    format: Option<Format>
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum HierarchyArg {
+    Flatten,
+    Keep
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Extractor {
+    Greedy,
+    Dag
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    Json,
+    Verilog,
+    Egglog
+}
+
+/// Everything that makes a command fail, each kind with its own exit code.
+#[derive(Debug)]
+enum CliError {
+    Import(NetlistError),
+    Rules(RuleError),
+    UnknownRuleSet(String),
+    EgglogModules(usize),
+    Verilog(VerilogError),
+    Output { path: String, source: std::io::Error }
+}
+
+impl CliError {
+    fn exit_code(&self) -> u8 {
+        match self {
+            CliError::UnknownRuleSet(_) | CliError::EgglogModules(_) => 2,  // like the usage errors reported by clap
+            CliError::Import(_) => 3,
+            CliError::Rules(_) => 4,
+            CliError::Verilog(_) | CliError::Output { .. } => 5
+        }
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::Import(err) => write!(f, "{}", err),
+            CliError::Rules(err) => write!(f, "{}", err),
+            CliError::UnknownRuleSet(name) => write!(f, "unknown rule set `{}`, expected basic, shift, unary, register, fold or a .rules file", name),
+            CliError::EgglogModules(count) => write!(f, "egglog output holds a single module, found {} with --hierarchy keep", count),
+            CliError::Verilog(err) => write!(f, "{}", err),
+            CliError::Output { path, source } => write!(f, "failed to write {}: {}", path, source)
+        }
+    }
+}
+
+// auxiliary functions
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let (digits, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(s.len()));
+    let value: f64 = digits.parse().map_err(|_| format!("invalid duration `{}`", s))?;
+    let secs = match unit {
+        "" | "s" => value,
+        "ms" => value / 1000.0,
+        "m" => value * 60.0,
+        "h" => value * 3600.0,
+        _ => return Err(format!("invalid duration unit `{}`, expected ms, s, m or h", unit))
+    };
+    Duration::try_from_secs_f64(secs).map_err(|_| format!("invalid duration `{}`", s))
+}
+
+fn progress_sink() -> ProgressSink {
+    Box::new(|event: &Progress| eprintln!("{}", serde_json::to_string(event).expect("Progress events are serializable")))
+}
+
+/// The imported modules, the top one first: only that one when instances are inlined,
+/// and every module it instantiates as well when they are kept.
+fn import(input: &InputArgs, progress: bool) -> Result<Vec<(String, NetlistDatabase)>, CliError> {
+    let clocks: Vec<&str> = input.clocks.iter().map(String::as_str).collect();
+    match input.hierarchy {
+        HierarchyArg::Flatten => {
+            let mut netlist = NetlistDatabase::default();
+            if progress {
+                netlist.set_progress(progress_sink());
+            }
+            netlist.build_from_json(&input.input, &input.top, &clocks, Hierarchy::Flatten).map_err(CliError::Import)?;
+            Ok(vec![(input.top.clone(), netlist)])
+        },
+        HierarchyArg::Keep => {
+            // the modules are imported before they get a sink, so only saturation reports progress
+            let mut modules = NetlistDatabase::build_modules_from_json(&input.input, &input.top, &clocks).map_err(CliError::Import)?;
+            if progress {
+                for (_, netlist) in modules.iter_mut() {
+                    netlist.set_progress(progress_sink());
+                }
+            }
+            Ok(modules)
+        }
+    }
+}
+
+/// One Yosys JSON netlist with the modules of all `netlists`.
+fn merge_json(netlists: impl Iterator<Item = serde_json::Value>) -> serde_json::Value {
+    let mut merged = serde_json::json!({ "creator": "eggrtl", "modules": {} });
+    for netlist in netlists {
+        if let (Some(modules), serde_json::Value::Object(more)) = (merged["modules"].as_object_mut(), &netlist["modules"]) {
+            modules.extend(more.clone());
+        }
+    }
+    merged
+}
+
+/// The egglog program of a single module; the programs of several modules would share net names.
+fn single_egglog(modules: &[(String, NetlistDatabase)]) -> Result<String, CliError> {
+    match modules {
+        [(_, netlist)] => Ok(netlist.export_egglog()),
+        _ => Err(CliError::EgglogModules(modules.len()))
+    }
+}
+
+fn print_stats(stats: &NetlistStats) {
+    println!("cells:      {}", stats.cells());
+    println!("  ay:       {}", stats.ay_cells);
+    println!("  aby:      {}", stats.aby_cells);
+    println!("  absy:     {}", stats.absy_cells);
+    println!("  dffs:     {}", stats.dffs);
+    println!("  opaque:   {}", stats.opaque_cells);
+    println!("wires:      {}", stats.wires);
+    println!("wirevecs:   {}", stats.wirevecs);
+    println!("constants:  {}", stats.const_words);
+    println!("unions:     {}", stats.unions);
+    println!("clocks:     {}", stats.clocks);
+    println!("netnames:   {}", stats.netnames);
+}
+
+fn write_output(
+    output: &OutputArgs, json: impl FnOnce() -> serde_json::Value, verilog: impl FnOnce() -> Result<String, VerilogError>, egglog: impl FnOnce() -> Result<String, CliError>
+) -> Result<(), CliError> {
+    let format = output.format.unwrap_or(match output.output.as_deref() {
+        Some(path) if path.ends_with(".v") => Format::Verilog,
+        Some(path) if path.ends_with(".egg") => Format::Egglog,
+        _ => Format::Json
+    });
+    let text = match format {
+        Format::Json => serde_json::to_string_pretty(&json()).expect("Netlist JSON is serializable"),
+        Format::Verilog => verilog().map_err(CliError::Verilog)?,
+        Format::Egglog => egglog()?
+    };
+    match &output.output {
+        Some(path) => std::fs::write(path, text).map_err(|source| CliError::Output { path: path.clone(), source }),
+        None => {
+            println!("{}", text);
+            Ok(())
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<(), CliError> {
+    match cli.command {
+        Command::Optimize { input, rules, iters, timeout, nodes, extract, output } => {
+            let mut config = RunConfig { iter_limit: iters, node_limit: nodes, time_limit: timeout, ..RunConfig::default() };
+            if !rules.is_empty() {
+                config.rule_sets.clear();
+                for name in rules.iter() {
+                    match RuleSet::from_name(name) {
+                        Some(rule_set) => config.rule_sets.push(rule_set),
+                        None if name.ends_with(".rules") => config.rule_files.push(RuleFile::load(name).map_err(CliError::Rules)?),
+                        None => return Err(CliError::UnknownRuleSet(name.clone()))
+                    }
+                }
+            }
+
+            let mut modules = import(&input, cli.progress)?;
+            let mode = match extract {
+                Extractor::Greedy => ExtractMode::Greedy,
+                Extractor::Dag => ExtractMode::Dag { time_limit: timeout }
+            };
+            let mut rows = Vec::new();
+            for (name, netlist) in modules.iter_mut() {
+                netlist.saturate(&config);
+                let report = netlist.extract(&AreaCost, mode);
+                info!(
+                    "Extracted module {} with cost {} (lower bound {}, gap {:.2}%)",
+                    name, report.extraction.cost, report.lower_bound, report.gap() * 100.0
+                );
+                rows.push(report.extraction.rows);
+            }
+            write_output(
+                &output,
+                || merge_json(modules.iter().zip(rows.iter()).map(|((name, netlist), rows)| netlist.export_rows_json(name, rows))),
+                || modules.iter().zip(rows.iter()).map(|((name, netlist), rows)| netlist.export_rows_verilog(name, rows)).collect::<Result<Vec<_>, _>>().map(|v| v.join("\n")),
+                || single_egglog(&modules)
+            )
+        },
+        Command::Stats { input } => {
+            let modules = import(&input, cli.progress)?;
+            for (name, netlist) in modules.iter() {
+                let stats = netlist.stats();
+                if modules.len() > 1 {
+                    println!("module {}:", name);
+                }
+                print_stats(&stats);
+            }
+            Ok(())
+        },
+        Command::Export { input, output } => {
+            let modules = import(&input, cli.progress)?;
+            write_output(
+                &output,
+                || merge_json(modules.iter().map(|(name, netlist)| netlist.export_json(name))),
+                || modules.iter().map(|(name, netlist)| netlist.export_verilog(name)).collect::<Result<Vec<_>, _>>().map(|v| v.join("\n")),
+                || single_egglog(&modules)
+            )
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let level = match cli.verbose {
+        0 => "warn",
+        1 => "info",
+        2 => "debug",
+        _ => "trace"
+    };
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(level)).init();
+    match run(cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            ExitCode::from(err.exit_code())
+        }
+    }
+}
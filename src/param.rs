@@ -0,0 +1,144 @@
+use std::fmt;
+
+
+/// A Yosys parameter as a four-state bit vector of any width, stored LSB first.
+/// Yosys writes these as strings of `0`, `1`, `x` and `z`, MSB first, and sometimes as plain numbers.
+#[derive(Clone, Debug, Default, Hash, PartialEq, Eq)]
+pub struct ParamBits(Vec<char>);
+
+impl ParamBits {
+    pub const BITS: &[char] = &['0', '1', 'x', 'z'];
+
+    /// Reads a parameter value, or returns `None` if it is neither a number nor a string of bits.
+    /// Numbers become 32 bits wide like Yosys integer parameters, or 64 bits if they do not fit.
+    pub fn from_json(value: &serde_json::Value) -> Option<Self> {
+        match value {
+            serde_json::Value::Number(num) => {
+                let value = num.as_i64()?;
+                let width = if i32::try_from(value).is_ok() { 32 } else { 64 };
+                Some(Self::from_i64(value, width))
+            },
+            serde_json::Value::String(s) => Self::parse(s),
+            _ => None
+        }
+    }
+
+    /// Parses an MSB-first string of `0`, `1`, `x` and `z`.
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.chars().all(|c| Self::BITS.contains(&c)) {
+            true => Some(Self(s.chars().rev().collect())),
+            false => None
+        }
+    }
+
+    /// The low `width` bits of `value` in two's complement, sign-extended beyond 64 bits.
+    pub fn from_i64(value: i64, width: usize) -> Self {
+        Self((0..width).map(|i| if (value >> i.min(63)) & 1 == 1 { '1' } else { '0' }).collect())
+    }
+
+    pub fn from_bits(bits: Vec<char>) -> Self {
+        Self(bits)
+    }
+
+    pub fn width(&self) -> usize {
+        self.0.len()
+    }
+
+    /// The bits, LSB first.
+    pub fn bits(&self) -> &[char] {
+        &self.0
+    }
+
+    /// Truncates or zero-extends to `width` bits.
+    pub fn resize(mut self, width: usize) -> Self {
+        self.0.resize(width, '0');
+        self
+    }
+
+    pub fn is_fully_defined(&self) -> bool {
+        self.0.iter().all(|b| *b == '0' || *b == '1')
+    }
+
+    /// The unsigned value, or `None` if a bit is `x` or `z` or the value does not fit in an `i64`.
+    pub fn to_i64(&self) -> Option<i64> {
+        if !self.is_fully_defined() || self.0.iter().skip(63).any(|b| *b == '1') {
+            return None;
+        }
+        Some(self.0.iter().take(63).enumerate().map(|(i, b)| ((*b == '1') as i64) << i).sum())
+    }
+
+    /// The parameter as Yosys writes it, an MSB-first string of bits.
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::Value::String(self.to_string())
+    }
+}
+
+impl fmt::Display for ParamBits {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for b in self.0.iter().rev() {
+            write!(f, "{}", b)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ParamBits;
+    use serde_json::json;
+
+    #[test]
+    fn keeps_every_bit_of_wide_parameters() {
+        let high = format!("1{}", "0".repeat(69));
+        let bits = ParamBits::parse(&high).unwrap();
+        assert_eq!(bits.width(), 70);
+        assert_eq!(bits.bits()[69], '1');
+        assert_eq!(bits.to_i64(), None);
+        assert_eq!(bits.to_string(), high);
+        assert_eq!(bits.to_json(), json!(high));
+
+        let low = format!("{}{}", "0".repeat(7), "1".repeat(63));
+        assert_eq!(ParamBits::parse(&low).unwrap().to_i64(), Some(i64::MAX));
+        assert_eq!(ParamBits::parse(&format!("1{}", "0".repeat(63))).unwrap().to_i64(), None);
+    }
+
+    #[test]
+    fn keeps_undefined_bits() {
+        let bits = ParamBits::parse("1x0z").unwrap();
+        assert_eq!(bits.bits(), &['z', '0', 'x', '1']);
+        assert!(!bits.is_fully_defined());
+        assert_eq!(bits.to_i64(), None);
+        assert_eq!(bits.to_string(), "1x0z");
+        assert_eq!(bits.clone().resize(6).to_string(), "001x0z");
+        assert_eq!(bits.resize(2).to_string(), "0z");
+
+        assert!(ParamBits::parse("0101").unwrap().is_fully_defined());
+        assert_eq!(ParamBits::parse("01a1"), None);
+        assert_eq!(ParamBits::parse("").unwrap().to_i64(), Some(0));
+    }
+
+    #[test]
+    fn reads_numbers_as_integer_parameters() {
+        let five = ParamBits::from_json(&json!(5)).unwrap();
+        assert_eq!((five.width(), five.to_i64()), (32, Some(5)));
+        let minus_one = ParamBits::from_json(&json!(-1)).unwrap();
+        assert_eq!((minus_one.width(), minus_one.to_i64()), (32, Some(0xffff_ffff)));
+        let wide = ParamBits::from_json(&json!(1i64 << 40)).unwrap();
+        assert_eq!((wide.width(), wide.to_i64()), (64, Some(1 << 40)));
+        let negative = ParamBits::from_json(&json!(-(1i64 << 40))).unwrap();
+        assert_eq!((negative.width(), negative.to_i64()), (64, None));
+
+        assert_eq!(ParamBits::from_json(&json!("0101")).unwrap().to_i64(), Some(5));
+        assert_eq!(ParamBits::from_json(&json!(u64::MAX)), None);
+        assert_eq!(ParamBits::from_json(&json!(true)), None);
+    }
+
+    #[test]
+    fn sign_extends_beyond_64_bits() {
+        let minus_one = ParamBits::from_i64(-1, 70);
+        assert_eq!(minus_one.to_string(), "1".repeat(70));
+        assert_eq!(ParamBits::from_i64(5, 70).to_i64(), Some(5));
+        assert_eq!(ParamBits::from_i64(-2, 3).to_string(), "110");
+        assert_eq!(ParamBits::from_i64(5, 70).resize(3), ParamBits::from_i64(5, 3));
+    }
+}
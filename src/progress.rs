@@ -0,0 +1,32 @@
+use serde::Serialize;
+use crate::core::NetlistDatabase;
+
+
+/// A machine-readable progress event, serialized as `{"event": "import", ...}`.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Progress {
+    /// Cells of `module` imported so far, sent about every percent. A streaming import does not know
+    /// how many cells are left, its `percent` is how far it got through the file.
+    Import { module: String, cells_done: usize, cells_total: Option<usize>, percent: f64, cells_per_sec: f64 },
+    /// A finished saturation iteration, with the rows and unions it added.
+    Iteration { iteration: usize, rows_added: usize, unions: usize, rebuilds: usize, rows: usize, elapsed_ms: u128 },
+    /// The end of a saturation run.
+    Saturated { stop_reason: String, iterations: usize, rows: usize, unions: usize, elapsed_ms: u128 }
+}
+
+/// Receives progress events, e.g. to write them as JSON lines for a dashboard.
+pub type ProgressSink = Box<dyn FnMut(&Progress)>;
+
+impl NetlistDatabase {
+    /// Sends progress events of later imports and saturation runs to `sink`.
+    pub fn set_progress(&mut self, sink: ProgressSink) {
+        self.progress = Some(sink);
+    }
+
+    pub(crate) fn report(&mut self, event: Progress) {
+        if let Some(sink) = self.progress.as_mut() {
+            sink(&event);
+        }
+    }
+}
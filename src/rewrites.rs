@@ -1,100 +1,534 @@
 use core_relations::{
-    ColumnId, Constraint, PlanStrategy, Value, WriteVal,
-    make_external_func
+    ColumnId, Constraint, CounterId, Database, ExternalFunctionId, PlanStrategy, QueryEntry, RuleBuilder,
+    RuleSetBuilder, TableId, Value, Variable, make_external_func
 };
-use std::{cell, fmt::Write, ops::Range};
-use crate::core::NetlistDatabase;
+use std::{
+    cmp::Ordering, collections::HashMap, ops::Range,
+    sync::{Arc, Mutex}
+};
+use bimap::BiHashMap;
+use crate::core::{NetlistDatabase, RegKind, VecContainer};
+use crate::fold::fold_cell;
 use numeric_id::NumericId;
 
 
+/// External functions shared by the rule sets, registered once per database.
+/// Guards return a dummy value on success and `None` to stop the rule for that match.
+pub(crate) struct ExternalFuncs {
+    pub(crate) union_vecs: ExternalFunctionId,  // (a, y) -> unit, unions a and y bitwise if their widths match
+    pub(crate) width_ge: ExternalFunctionId,    // (a, b) -> unit if |a| >= |b|
+    pub(crate) width_gt: ExternalFunctionId,    // (a, b) -> unit if |a| > |b|
+    pub(crate) width_eq: ExternalFunctionId,    // (a, b) -> unit if |a| == |b|
+    pub(crate) is_bit: ExternalFunctionId,      // (a) -> unit if |a| == 1
+    pub(crate) fresh_like: ExternalFunctionId,  // (a) -> a wirevec of |a| fresh wires
+    pub(crate) fresh_for: ExternalFunctionId,   // (ty, ins.., w) -> the output wirevec of ty(ins..), created with |w| fresh wires on first use
+    pub(crate) is_zero: ExternalFunctionId,     // (a) -> unit if a is a constant zero
+    pub(crate) is_const: ExternalFunctionId,    // (a) -> unit if every bit of a is a constant 0 or 1
+    pub(crate) msb_zero: ExternalFunctionId,    // (a) -> unit if the top bit of a is a constant 0, so sign and zero extension agree
+    pub(crate) is_one_u: ExternalFunctionId,    // (a) -> unit if a is the constant 1 read as unsigned
+    pub(crate) is_one_s: ExternalFunctionId,    // (a) -> unit if a is the constant 1 read as signed
+    pub(crate) zero_like: ExternalFunctionId,   // (a) -> a constant zero as wide as a
+    pub(crate) fold: ExternalFunctionId,        // (ty, ins.., y) -> the value of ty(ins..) as wide as y if all inputs are constants
+    pub(crate) log2_const: ExternalFunctionId,  // (a) -> log2(a) as a constant wirevec if a is a constant power of two
+    pub(crate) pow2_const: ExternalFunctionId,  // (k) -> 2^k as a constant wirevec if k is a constant
+    pub(crate) add_consts: ExternalFunctionId   // (a, b) -> a + b as a constant wirevec if both are constants
+}
+
+/// A column of a rule pattern: a variable, shared by every column with the same name, or a constant.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Term<'a> {
+    Var(&'a str),
+    Const(Value)
+}
+use Term::{Const, Var};
+
+/// An atom of a rule pattern; `terms` covers every column of `table` except the trailing timestamp.
+pub(crate) struct PatAtom<'a> {
+    pub(crate) table: TableId,
+    pub(crate) terms: Vec<Term<'a>>
+}
+
+pub(crate) fn atom<'a>(table: TableId, terms: &[Term<'a>]) -> PatAtom<'a> {
+    PatAtom { table, terms: terms.to_vec() }
+}
+
+pub(crate) type Bindings<'a> = HashMap<&'a str, Variable>;
+
+/// Adds a rule for `pattern` as one variant per atom, for seminaive evaluation: variant i
+/// matches atom i against the recent range, the atoms before it against older rows and
+/// the atoms after it against all rows, so each match with a recent atom is found once.
+/// `rhs` adds the actions of every variant, given the variables of the pattern.
+pub(crate) fn add_seminaive_rule<'a>(
+    rsb: &mut RuleSetBuilder, strategy: PlanStrategy, recent_range: &Range<Value>,
+    pattern: &[PatAtom<'a>], rhs: impl Fn(&mut RuleBuilder, &Bindings<'a>)
+) {
+    let old_range = Value::new(0)..recent_range.start;
+    let all_range = Value::new(0)..recent_range.end;
+    for recent in 0..pattern.len() {
+        let mut lhs = rsb.new_rule();
+        lhs.set_plan_strategy(strategy);
+        let mut vars = Bindings::new();
+        for (i, atom) in pattern.iter().enumerate() {
+            let range = match i.cmp(&recent) {
+                Ordering::Less => &old_range,
+                Ordering::Equal => recent_range,
+                Ordering::Greater => &all_range
+            };
+            let ts_col = ColumnId::from_usize(atom.terms.len());
+            let mut entries: Vec<QueryEntry> = Vec::new();
+            let mut constraints = vec![
+                Constraint::GeConst{col: ts_col, val: range.start},
+                Constraint::LtConst{col: ts_col, val: range.end}    // time range
+            ];
+            for (col, term) in atom.terms.iter().enumerate() {
+                match term {
+                    Var(name) => entries.push((*vars.entry(*name).or_insert_with(|| lhs.new_var())).into()),
+                    Const(val) => {
+                        entries.push(lhs.new_var().into());
+                        constraints.push(Constraint::EqConst{col: ColumnId::from_usize(col), val: *val});
+                    }
+                }
+            }
+            entries.push(lhs.new_var().into());    // timestamp
+            lhs.add_atom(atom.table, &entries, &constraints).unwrap();
+        }
+
+        let mut rule = lhs.build();
+        rhs(&mut rule, &vars);
+        rule.build();
+    }
+}
+
+// auxiliary functions
+pub(crate) fn const_bits_value(bits: &[Value], c0: Value, c1: Value) -> Option<u64> {
+    if bits.len() > 64 {
+        return None;
+    }
+    let mut value = 0;
+    for (i, bit) in bits.iter().enumerate() {
+        match *bit {
+            b if b == c1 => value |= 1 << i,
+            b if b == c0 => {},
+            _ => return None
+        }
+    }
+    Some(value)
+}
+
+fn const_value_bits(value: u64, c0: Value, c1: Value) -> Vec<Value> {
+    let width = std::cmp::max(64 - value.leading_zeros() as usize, 1);
+    (0..width).map(|i| if (value >> i) & 1 == 1 { c1 } else { c0 }).collect()
+}
+
+
 impl NetlistDatabase {
-    pub fn rewrite_basic_all(&mut self, strategy: PlanStrategy, recent_range: Range<Value>) {
-        let old_range = Value::new(0)..recent_range.start;
-        let all_range = Value::new(0)..recent_range.end;
-        let next_ts = recent_range.end;
+    pub(crate) fn register_external_funcs(
+        db: &mut Database, id_counter: CounterId, ts_counter: CounterId, displaced: TableId,
+        types: &BiHashMap<String, Value>, c0: Value, c1: Value
+    ) -> ExternalFuncs {
+        let unit = Value::new(0);
+
+        let union_vecs = db.add_external_function(make_external_func(move |state, args| -> Option<Value> {
+            let vec1 = state.container_values().get_val::<VecContainer>(args[0])?.0.clone();
+            let vec2 = state.container_values().get_val::<VecContainer>(args[1])?.0.clone();
+            if vec1.len() != vec2.len() {
+                return None;
+            }
+            let next_ts = Value::from_usize(state.read_counter(ts_counter));
+            for (elem1, elem2) in vec1.iter().zip(vec2.iter()) {    // union each pair of elements
+                if elem1 != elem2 {
+                    state.stage_insert(displaced, &[*elem1, *elem2, next_ts]);
+                }
+            }
+            Some(unit)
+        }));
+
+        let width_ge = db.add_external_function(make_external_func(move |state, args| -> Option<Value> {
+            let len1 = state.container_values().get_val::<VecContainer>(args[0])?.0.len();
+            let len2 = state.container_values().get_val::<VecContainer>(args[1])?.0.len();
+            (len1 >= len2).then_some(unit)
+        }));
+
+        let width_gt = db.add_external_function(make_external_func(move |state, args| -> Option<Value> {
+            let len1 = state.container_values().get_val::<VecContainer>(args[0])?.0.len();
+            let len2 = state.container_values().get_val::<VecContainer>(args[1])?.0.len();
+            (len1 > len2).then_some(unit)
+        }));
+
+        let width_eq = db.add_external_function(make_external_func(move |state, args| -> Option<Value> {
+            let len1 = state.container_values().get_val::<VecContainer>(args[0])?.0.len();
+            let len2 = state.container_values().get_val::<VecContainer>(args[1])?.0.len();
+            (len1 == len2).then_some(unit)
+        }));
+
+        let is_bit = db.add_external_function(make_external_func(move |state, args| -> Option<Value> {
+            let len = state.container_values().get_val::<VecContainer>(args[0])?.0.len();
+            (len == 1).then_some(unit)
+        }));
+
+        let fresh_like = db.add_external_function(make_external_func(move |state, args| -> Option<Value> {
+            let len = state.container_values().get_val::<VecContainer>(args[0])?.0.len();
+            let wires = (0..len).map(|_| Value::from_usize(state.inc_counter(id_counter))).collect();
+            Some(state.container_values().register_val(VecContainer(wires), state))
+        }));
+
+        // remembers the wirevecs made for new cells, so matching the same pattern again reuses them
+        let outputs: Arc<Mutex<HashMap<Vec<Value>, Value>>> = Arc::default();
+        let fresh_for = db.add_external_function(make_external_func(move |state, args| -> Option<Value> {
+            let (width, cell) = args.split_last()?;
+            let mut outputs = outputs.lock().unwrap();
+            if let Some(vec) = outputs.get(cell) {
+                return Some(*vec);
+            }
+            let len = state.container_values().get_val::<VecContainer>(*width)?.0.len();
+            let wires = (0..len).map(|_| Value::from_usize(state.inc_counter(id_counter))).collect();
+            let vec = state.container_values().register_val(VecContainer(wires), state);
+            outputs.insert(cell.to_vec(), vec);
+            Some(vec)
+        }));
+
+        let is_zero = db.add_external_function(make_external_func(move |state, args| -> Option<Value> {
+            let zero = state.container_values().get_val::<VecContainer>(args[0])?.0.iter().all(|b| *b == c0);
+            zero.then_some(unit)
+        }));
+
+        let is_const = db.add_external_function(make_external_func(move |state, args| -> Option<Value> {
+            let constant = state.container_values().get_val::<VecContainer>(args[0])?.0.iter().all(|b| *b == c0 || *b == c1);
+            constant.then_some(unit)
+        }));
+
+        let msb_zero = db.add_external_function(make_external_func(move |state, args| -> Option<Value> {
+            let msb = state.container_values().get_val::<VecContainer>(args[0])?.0.last().copied();
+            (msb.is_none() || msb == Some(c0)).then_some(unit)
+        }));
+
+        let is_one_u = db.add_external_function(make_external_func(move |state, args| -> Option<Value> {
+            let value = const_bits_value(&state.container_values().get_val::<VecContainer>(args[0])?.0, c0, c1)?;
+            (value == 1).then_some(unit)
+        }));
+
+        let is_one_s = db.add_external_function(make_external_func(move |state, args| -> Option<Value> {
+            let bits = &state.container_values().get_val::<VecContainer>(args[0])?.0;
+            let one = bits.len() >= 2 && const_bits_value(bits, c0, c1) == Some(1);  // a single 1 bit is -1
+            one.then_some(unit)
+        }));
+
+        let zero_like = db.add_external_function(make_external_func(move |state, args| -> Option<Value> {
+            let len = state.container_values().get_val::<VecContainer>(args[0])?.0.len();
+            Some(state.container_values().register_val(VecContainer(vec![c0; len]), state))
+        }));
+
+        let type_names: HashMap<Value, String> = types.iter().map(|(name, ty)| (*ty, name.clone())).collect();
+        let fold = db.add_external_function(make_external_func(move |state, args| -> Option<Value> {
+            let (y, cell) = args.split_last()?;
+            let (ty, ins) = cell.split_first()?;
+            let mut values = Vec::new();
+            for vec in ins {
+                let bits = &state.container_values().get_val::<VecContainer>(*vec)?.0;
+                values.push((const_bits_value(bits, c0, c1)?, bits.len()));
+            }
+            let width = state.container_values().get_val::<VecContainer>(*y)?.0.len();
+            let value = fold_cell(type_names.get(ty)?, &values, width)?;
+            let bits = (0..width).map(|i| if (value >> i) & 1 == 1 { c1 } else { c0 }).collect();
+            Some(state.container_values().register_val(VecContainer(bits), state))
+        }));
+
+        let log2_const = db.add_external_function(make_external_func(move |state, args| -> Option<Value> {
+            let value = const_bits_value(&state.container_values().get_val::<VecContainer>(args[0])?.0, c0, c1)?;
+            if !value.is_power_of_two() {
+                return None;
+            }
+            let bits = const_value_bits(value.trailing_zeros() as u64, c0, c1);
+            Some(state.container_values().register_val(VecContainer(bits), state))
+        }));
 
-        // this is a test
-        let func_id = self.db.add_external_function(make_external_func(move |state, args| -> Option<Value> {
-            // insert or lookup
-            println!("External function called: {:?}", args);
-            None
+        let pow2_const = db.add_external_function(make_external_func(move |state, args| -> Option<Value> {
+            let value = const_bits_value(&state.container_values().get_val::<VecContainer>(args[0])?.0, c0, c1)?;
+            if value >= 64 {
+                return None;
+            }
+            let bits = const_value_bits(1 << value, c0, c1);
+            Some(state.container_values().register_val(VecContainer(bits), state))
+        }));
+
+        let add_consts = db.add_external_function(make_external_func(move |state, args| -> Option<Value> {
+            let value1 = const_bits_value(&state.container_values().get_val::<VecContainer>(args[0])?.0, c0, c1)?;
+            let value2 = const_bits_value(&state.container_values().get_val::<VecContainer>(args[1])?.0, c0, c1)?;
+            let bits = const_value_bits(value1.checked_add(value2)?, c0, c1);
+            Some(state.container_values().register_val(VecContainer(bits), state))
         }));
 
+        ExternalFuncs {
+            union_vecs, width_ge, width_gt, width_eq, is_bit, fresh_like, fresh_for, is_zero, is_const, msb_zero,
+            is_one_u, is_one_s, zero_like, fold,
+            log2_const, pow2_const, add_consts
+        }
+    }
+
+    pub fn rewrite_basic_all(&mut self, strategy: PlanStrategy, recent_range: Range<Value>) -> bool {
+        let next_ts = recent_range.end;
+        let funcs = &self.funcs;
+        let aby = self.aby_cells;
+        let ty = |name: &str| *self.types.get_by_left(name).unwrap();
+
         let mut rsb = self.db.new_rule_set();
 
         // aby_assoc_to_right
-        // (a + b) + c => a + (b + c)
-        let assoc_types = ["$adds", "$addu"];
-        for assoc_type in assoc_types {
-            for (l_range, r_range) in [ // seminaive evaluation
-                (all_range.clone(), recent_range.clone()),
-                (recent_range.clone(), old_range.clone())
-            ] {
-                let mut aby_assoc_to_right_lhs = rsb.new_rule();
-                aby_assoc_to_right_lhs.set_plan_strategy(strategy);
-                let cell_type = aby_assoc_to_right_lhs.new_var();
-                let a = aby_assoc_to_right_lhs.new_var();
-                let b = aby_assoc_to_right_lhs.new_var();
-                let tmp = aby_assoc_to_right_lhs.new_var();
-                let c = aby_assoc_to_right_lhs.new_var();
-                let y = aby_assoc_to_right_lhs.new_var();
-                let t1 = aby_assoc_to_right_lhs.new_var();
-                let t2 = aby_assoc_to_right_lhs.new_var();
-                aby_assoc_to_right_lhs.add_atom(
-                    self.aby_cells,
-                    &[cell_type.into(), a.into(), b.into(), tmp.into(), t1.into()],
-                    &[
-                        Constraint::GeConst{col: ColumnId::new(4), val: l_range.start},
-                        Constraint::LtConst{col: ColumnId::new(4), val: l_range.end},   // time range
-                        Constraint::EqConst{col: ColumnId::new(0), val: *self.types.get_by_left(assoc_type).unwrap()}, // cell type
-                    ]
-                ).unwrap();
-                aby_assoc_to_right_lhs.add_atom(
-                    self.aby_cells,
-                    &[cell_type.into(), tmp.into(), c.into(), y.into(), t2.into()],
-                    &[
-                        Constraint::GeConst{col: ColumnId::new(4), val: r_range.start},
-                        Constraint::LtConst{col: ColumnId::new(4), val: r_range.end},   // time range
-                        Constraint::EqConst{col: ColumnId::new(0), val: *self.types.get_by_left(assoc_type).unwrap()}, // cell type
-                    ]
-                ).unwrap();
+        // (a + b) + c => a + (b + c), if the intermediate result is at least as wide as the final one
+        // b + c gets the width of the final result, so no carry (or product bit) is dropped early;
+        // both cells have the same type, so every operand is extended the same way before and after
+        for assoc_type in ["$adds", "$addu", "$muls", "$mulu", "$ands", "$andu", "$ors", "$oru", "$xors", "$xoru"] {
+            let t = ty(assoc_type);
+            add_seminaive_rule(&mut rsb, strategy, &recent_range, &[
+                atom(aby, &[Const(t), Var("a"), Var("b"), Var("tmp")]),
+                atom(aby, &[Const(t), Var("tmp"), Var("c"), Var("y")])
+            ], |rhs, v| {
+                rhs.call_external(funcs.width_ge, &[v["tmp"].into(), v["y"].into()]).unwrap();
+                let bc = rhs.call_external(funcs.fresh_for, &[t.into(), v["b"].into(), v["c"].into(), v["y"].into()]).unwrap();
+                rhs.insert(aby, &[t.into(), v["b"].into(), v["c"].into(), bc.into(), next_ts.into()]).unwrap();
+                rhs.insert(aby, &[t.into(), v["a"].into(), bc.into(), v["y"].into(), next_ts.into()]).unwrap();
+            });
+        }
 
-                let mut aby_assoc_to_right_rhs = aby_assoc_to_right_lhs.build();
-                aby_assoc_to_right_rhs.call_external(func_id, &[]).unwrap();    // test
-            }
+        // aby_drop_sign
+        // a +s b => a +u b, if both operands are at least as wide as the result
+        // the low bits of these cells only depend on the low bits of their operands, so extension never shows
+        for (signed_type, unsigned_type) in [
+            ("$adds", "$addu"), ("$subs", "$subu"), ("$muls", "$mulu"),
+            ("$ands", "$andu"), ("$ors", "$oru"), ("$xors", "$xoru"), ("$nands", "$nandu"), ("$nors", "$noru"), ("$xnors", "$xnoru")
+        ] {
+            let unsigned = ty(unsigned_type);
+            add_seminaive_rule(&mut rsb, strategy, &recent_range, &[
+                atom(aby, &[Const(ty(signed_type)), Var("a"), Var("b"), Var("y")])
+            ], |rhs, v| {
+                rhs.call_external(funcs.width_ge, &[v["a"].into(), v["y"].into()]).unwrap();
+                rhs.call_external(funcs.width_ge, &[v["b"].into(), v["y"].into()]).unwrap();
+                rhs.insert(aby, &[unsigned.into(), v["a"].into(), v["b"].into(), v["y"].into(), next_ts.into()]).unwrap();
+            });
         }
 
         // aby_comm
         // a + b => b + a
-        let comm_types = ["$adds", "$addu", "$muls", "$mulu"];
-        for comm_type in comm_types {
-            let mut aby_comm_lhs = rsb.new_rule();
-            aby_comm_lhs.set_plan_strategy(strategy);
-            let cell_type = aby_comm_lhs.new_var();
-            let a = aby_comm_lhs.new_var();
-            let b = aby_comm_lhs.new_var();
-            let y = aby_comm_lhs.new_var();
-            let t = aby_comm_lhs.new_var();
-            aby_comm_lhs.add_atom(
-                self.aby_cells,
-                &[cell_type.into(), a.into(), b.into(), y.into(), t.into()],
-                &[
-                    Constraint::EqConst{col: ColumnId::new(4), val: recent_range.start},    // current timestamp
-                    Constraint::EqConst{col: ColumnId::new(0), val: *self.types.get_by_left(comm_type).unwrap()}, // cell type
-                ]
-            ).unwrap();
-
-            let mut aby_comm_rhs = aby_comm_lhs.build();
-            aby_comm_rhs.insert(
-                self.aby_cells,
-                &[cell_type.into(), b.into(), a.into(), y.into(), next_ts.into()]
-            ).unwrap();
-            aby_comm_rhs.call_external(func_id, &[]).unwrap();  // test
-            aby_comm_rhs.build();
+        for comm_type in ["$adds", "$addu", "$muls", "$mulu"] {
+            let t = ty(comm_type);
+            add_seminaive_rule(&mut rsb, strategy, &recent_range, &[
+                atom(aby, &[Const(t), Var("a"), Var("b"), Var("y")])
+            ], |rhs, v| {
+                rhs.insert(aby, &[t.into(), v["b"].into(), v["a"].into(), v["y"].into(), next_ts.into()]).unwrap();
+            });
+        }
+
+        // finish and run the rule set
+        let rs = rsb.build();
+        self.db.run_rule_set(&rs)
+    }
+
+    pub fn rewrite_shift_all(&mut self, strategy: PlanStrategy, recent_range: Range<Value>) -> bool {
+        let next_ts = recent_range.end;
+        let funcs = &self.funcs;
+        let aby = self.aby_cells;
+        let ty = |name: &str| *self.types.get_by_left(name).unwrap();
+
+        let mut rsb = self.db.new_rule_set();
+
+        // aby_mul_to_shl
+        // a * 2^k => a << k
+        // aby_shl_to_mul
+        // a << k => a * 2^k
+        for (from_type, to_type, const_func) in [
+            ("$mulu", "$shlu", funcs.log2_const),
+            ("$shlu", "$mulu", funcs.pow2_const)
+        ] {
+            let (from, to) = (ty(from_type), ty(to_type));
+            add_seminaive_rule(&mut rsb, strategy, &recent_range, &[
+                atom(aby, &[Const(from), Var("a"), Var("b"), Var("y")])
+            ], |rhs, v| {
+                let c = rhs.call_external(const_func, &[v["b"].into()]).unwrap();
+                rhs.insert(aby, &[to.into(), v["a"].into(), c.into(), v["y"].into(), next_ts.into()]).unwrap();
+            });
+        }
+
+        // aby_shift_by_zero
+        // a << 0 => a, if a and the result have the same width
+        let shift_types = ["$shls", "$shlu", "$shrs", "$shru", "$sshls", "$sshlu", "$sshrs", "$sshru", "$shifts", "$shiftu", "$shiftxs", "$shiftxu"];
+        for shift_type in shift_types {
+            add_seminaive_rule(&mut rsb, strategy, &recent_range, &[
+                atom(aby, &[Const(ty(shift_type)), Var("a"), Var("b"), Var("y")])
+            ], |rhs, v| {
+                rhs.call_external(funcs.is_zero, &[v["b"].into()]).unwrap();
+                rhs.call_external(funcs.union_vecs, &[v["a"].into(), v["y"].into()]).unwrap();
+            });
+        }
+
+        // aby_shift_compose
+        // (a << k1) << k2 => a << (k1 + k2), if the intermediate result is at least as wide as the final one
+        // (a >> k1) >> k2 => a >> (k1 + k2), if the intermediate result is at least as wide as a
+        for (compose_type, left_shift) in [("$shlu", true), ("$shls", true), ("$shru", false)] {
+            let t = ty(compose_type);
+            add_seminaive_rule(&mut rsb, strategy, &recent_range, &[
+                atom(aby, &[Const(t), Var("a"), Var("k1"), Var("tmp")]),
+                atom(aby, &[Const(t), Var("tmp"), Var("k2"), Var("y")])
+            ], |rhs, v| {
+                let bound = if left_shift { v["y"] } else { v["a"] };
+                rhs.call_external(funcs.width_ge, &[v["tmp"].into(), bound.into()]).unwrap();
+                let k = rhs.call_external(funcs.add_consts, &[v["k1"].into(), v["k2"].into()]).unwrap();
+                rhs.insert(aby, &[t.into(), v["a"].into(), k.into(), v["y"].into(), next_ts.into()]).unwrap();
+            });
+        }
+
+        // finish and run the rule set
+        let rs = rsb.build();
+        self.db.run_rule_set(&rs)
+    }
+
+    pub fn rewrite_unary_all(&mut self, strategy: PlanStrategy, recent_range: Range<Value>) -> bool {
+        let next_ts = recent_range.end;
+        let funcs = &self.funcs;
+        let (ay, aby) = (self.ay_cells, self.aby_cells);
+        let ty = |name: &str| *self.types.get_by_left(name).unwrap();
+
+        let mut rsb = self.db.new_rule_set();
+
+        // ay_neg_neg
+        // -(-a) => a, if the intermediate result is at least as wide as the final one
+        for neg_type in ["$negs", "$negu"] {
+            let t = ty(neg_type);
+            add_seminaive_rule(&mut rsb, strategy, &recent_range, &[
+                atom(ay, &[Const(t), Var("a"), Var("tmp")]),
+                atom(ay, &[Const(t), Var("tmp"), Var("y")])
+            ], |rhs, v| {
+                rhs.call_external(funcs.width_ge, &[v["tmp"].into(), v["y"].into()]).unwrap();
+                rhs.call_external(funcs.union_vecs, &[v["a"].into(), v["y"].into()]).unwrap();
+            });
+        }
+
+        // aby_sub_to_add_neg
+        // a - b => a + (-b), where -b is as wide as the result
+        for (sub_type, neg_type, add_type) in [("$subs", "$negs", "$adds"), ("$subu", "$negu", "$addu")] {
+            let (neg, add) = (ty(neg_type), ty(add_type));
+            add_seminaive_rule(&mut rsb, strategy, &recent_range, &[
+                atom(aby, &[Const(ty(sub_type)), Var("a"), Var("b"), Var("y")])
+            ], |rhs, v| {
+                let neg_b = rhs.call_external(funcs.fresh_like, &[v["y"].into()]).unwrap();
+                rhs.insert(ay, &[neg.into(), v["b"].into(), neg_b.into(), next_ts.into()]).unwrap();
+                rhs.insert(aby, &[add.into(), v["a"].into(), neg_b.into(), v["y"].into(), next_ts.into()]).unwrap();
+            });
+        }
+
+        // aby_add_neg_to_sub
+        // a + (-b) => a - b, if -b is at least as wide as the result
+        for (sub_type, neg_type, add_type) in [("$subs", "$negs", "$adds"), ("$subu", "$negu", "$addu")] {
+            let sub = ty(sub_type);
+            add_seminaive_rule(&mut rsb, strategy, &recent_range, &[
+                atom(ay, &[Const(ty(neg_type)), Var("b"), Var("neg_b")]),
+                atom(aby, &[Const(ty(add_type)), Var("a"), Var("neg_b"), Var("y")])
+            ], |rhs, v| {
+                rhs.call_external(funcs.width_ge, &[v["neg_b"].into(), v["y"].into()]).unwrap();
+                rhs.insert(aby, &[sub.into(), v["a"].into(), v["b"].into(), v["y"].into(), next_ts.into()]).unwrap();
+            });
+        }
+
+        // ay_reduce_bit
+        // reduce_or(a) => a, if a and the result are single bits (likewise for and, xor and bool)
+        for reduce_type in ["$reduce_and", "$reduce_or", "$reduce_xor", "$reduce_bool"] {
+            add_seminaive_rule(&mut rsb, strategy, &recent_range, &[
+                atom(ay, &[Const(ty(reduce_type)), Var("a"), Var("y")])
+            ], |rhs, v| {
+                rhs.call_external(funcs.is_bit, &[v["a"].into()]).unwrap();
+                rhs.call_external(funcs.union_vecs, &[v["a"].into(), v["y"].into()]).unwrap();
+            });
+        }
+
+        // finish and run the rule set
+        let rs = rsb.build();
+        self.db.run_rule_set(&rs)
+    }
+
+    pub fn rewrite_register_all(&mut self, strategy: PlanStrategy, recent_range: Range<Value>) -> bool {
+        let next_ts = recent_range.end;
+        let empty = self.empty_vec;
+        let mux = *self.types.get_by_left("$mux").unwrap();
+
+        // (control polarity, dff kind, dffe kind, sdff kind) for both clock edges
+        let mut kinds = Vec::new();
+        for clk_pol in [true, false] {
+            for ctrl_pol in [true, false] {
+                kinds.push((
+                    ctrl_pol,
+                    self.create_or_lookup_reg_kind(RegKind::new("$dff", &[("CLK_POLARITY", clk_pol)])),
+                    self.create_or_lookup_reg_kind(RegKind::new("$dffe", &[("CLK_POLARITY", clk_pol), ("EN_POLARITY", ctrl_pol)])),
+                    self.create_or_lookup_reg_kind(RegKind::new("$sdff", &[("CLK_POLARITY", clk_pol), ("SRST_POLARITY", ctrl_pol)]))
+                ));
+            }
+        }
+
+        let funcs = &self.funcs;
+        let (absy, dffs) = (self.absy_cells, self.dffs);
+        let mut rsb = self.db.new_rule_set();
+
+        // the clock wirevec is copied from the matched register, and the kind carries its edge,
+        // so no rule moves logic between clock domains. Only enables on the D side are handled:
+        // a gated clock stays a clock of its own, it is never turned back into an enable.
+        for (ctrl_pol, dff_kind, dffe_kind, sdff_kind) in kinds {
+            // dffe_to_dff
+            // dffe(en, d) => dff(mux(q, d, en)), the disabled register keeps its value
+            add_seminaive_rule(&mut rsb, strategy, &recent_range, &[
+                atom(dffs, &[Const(dffe_kind), Var("clk"), Var("en"), Var("rst"), Var("rst_val"), Var("d"), Var("q")])
+            ], |rhs, v| {
+                let next = rhs.call_external(funcs.fresh_like, &[v["q"].into()]).unwrap();
+                let (a, b) = if ctrl_pol { (v["q"], v["d"]) } else { (v["d"], v["q"]) };
+                rhs.insert(absy, &[mux.into(), a.into(), b.into(), v["en"].into(), next.into(), next_ts.into()]).unwrap();
+                rhs.insert(
+                    dffs,
+                    &[dff_kind.into(), v["clk"].into(), empty.into(), empty.into(), empty.into(), next.into(), v["q"].into(), next_ts.into()]
+                ).unwrap();
+            });
+
+            // sdff_to_dff
+            // sdff(srst, val, d) => dff(mux(d, val, srst))
+            add_seminaive_rule(&mut rsb, strategy, &recent_range, &[
+                atom(dffs, &[Const(sdff_kind), Var("clk"), Var("en"), Var("rst"), Var("rst_val"), Var("d"), Var("q")])
+            ], |rhs, v| {
+                let next = rhs.call_external(funcs.fresh_like, &[v["q"].into()]).unwrap();
+                let (a, b) = if ctrl_pol { (v["d"], v["rst_val"]) } else { (v["rst_val"], v["d"]) };
+                rhs.insert(absy, &[mux.into(), a.into(), b.into(), v["rst"].into(), next.into(), next_ts.into()]).unwrap();
+                rhs.insert(
+                    dffs,
+                    &[dff_kind.into(), v["clk"].into(), empty.into(), empty.into(), empty.into(), next.into(), v["q"].into(), next_ts.into()]
+                ).unwrap();
+            });
+
+            // dff_mux_to_dffe and dff_mux_to_sdff
+            // dff(mux(q, d, en)) => dffe(en, d), dff(mux(d, val, srst)) => sdff(srst, val, d) if val is a constant
+            for to_sdff in [false, true] {
+                // the held value is selected when an enable is inactive or a reset is active
+                let held = if to_sdff { Var("held") } else { Var("q") };
+                let (a, b) = match ctrl_pol != to_sdff {
+                    true => (held, Var("d")),
+                    false => (Var("d"), held)
+                };
+                add_seminaive_rule(&mut rsb, strategy, &recent_range, &[
+                    atom(dffs, &[Const(dff_kind), Var("clk"), Var("en"), Var("rst"), Var("rst_val"), Var("next"), Var("q")]),
+                    atom(absy, &[Const(mux), a, b, Var("sel"), Var("next")])
+                ], |rhs, v| {
+                    rhs.call_external(funcs.is_bit, &[v["sel"].into()]).unwrap();
+                    let (kind, en, rst, rst_val): (Value, QueryEntry, QueryEntry, QueryEntry) = match to_sdff {
+                        true => {
+                            rhs.call_external(funcs.is_const, &[v["held"].into()]).unwrap();
+                            (sdff_kind, empty.into(), v["sel"].into(), v["held"].into())
+                        },
+                        false => (dffe_kind, v["sel"].into(), empty.into(), empty.into())
+                    };
+                    rhs.insert(
+                        dffs,
+                        &[kind.into(), v["clk"].into(), en, rst, rst_val, v["d"].into(), v["q"].into(), next_ts.into()]
+                    ).unwrap();
+                });
+            }
         }
 
         // finish and run the rule set
         let rs = rsb.build();
-        self.db.run_rule_set(&rs);
+        self.db.run_rule_set(&rs)
     }
 }
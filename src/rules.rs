@@ -0,0 +1,530 @@
+//! Rewrite rules written as s-expressions, e.g.
+//!
+//! ```text
+//! ; (a + b) + c => a + (b + c)
+//! (rewrite ($addu ($addu a b) c) ($addu a ($addu b c)))
+//! (birewrite ($mulu a b) ($mulu b a))
+//! (rewrite ($shlu a k) a :when ((is-zero k)))
+//! (rewrite ($mux a b 1) b)
+//! ```
+//!
+//! Operators are the internal cell types (`$addu`, `$negs`, `$ltu`, `$mux`, ...), which carry the
+//! signedness of their operands, variables stand for wirevecs and non-negative integer literals, on
+//! the left-hand side only, for constant wirevecs of that value.
+//! Every cell nested in a left-hand side must be at least as wide as the matched root, and every
+//! cell nested in a right-hand side is created as wide as the root, so truncation never moves.
+//! This keeps rules over cells whose low result bits only depend on the low operand bits (adds,
+//! multiplications, bitwise cells) sound; rules over other cells should add guards on operand widths:
+//!
+//! - `(width-ge a b)`, `(width-gt a b)`, `(width-eq a b)`: compare the widths of `a` and `b`
+//! - `(is-bit a)`: `a` is a single bit
+//! - `(is-zero a)`, `(is-const a)`: `a` is the constant zero, or any constant
+//! - `(msb-zero a)`: the top bit of `a` is 0, so sign and zero extension of `a` agree
+
+use bimap::BiHashMap;
+use core_relations::{PlanStrategy, QueryEntry, RuleBuilder, TableId, Value, Variable};
+use std::{collections::HashSet, ops::Range};
+use crate::core::NetlistDatabase;
+use crate::error::{Pos, RuleError};
+use crate::rewrites::{Bindings, ExternalFuncs, PatAtom, Term, add_seminaive_rule, atom};
+
+
+// (name, number of operands)
+const GUARDS: &[(&str, usize)] = &[
+    ("width-ge", 2), ("width-gt", 2), ("width-eq", 2), ("is-bit", 1), ("is-zero", 1), ("is-const", 1), ("msb-zero", 1)
+];
+
+#[derive(Clone, Debug)]
+enum Sexp {
+    Symbol(String, Pos),
+    List(Vec<Sexp>, Pos)
+}
+
+impl Sexp {
+    fn pos(&self) -> Pos {
+        match self {
+            Sexp::Symbol(_, pos) | Sexp::List(_, pos) => *pos
+        }
+    }
+}
+
+/// One side of a rule: a variable or a cell applied to sub-patterns.
+#[derive(Clone, Debug)]
+pub enum Pattern {
+    Var(String, Pos),
+    Lit(i64, Pos),
+    Cell { op: String, args: Vec<Pattern>, pos: Pos }
+}
+
+impl Pattern {
+    fn vars<'a>(&'a self, vars: &mut Vec<(&'a str, Pos)>) {
+        match self {
+            Pattern::Var(name, pos) => vars.push((name.as_str(), *pos)),
+            Pattern::Lit(..) => {},
+            Pattern::Cell { args, .. } => args.iter().for_each(|a| a.vars(vars))
+        }
+    }
+
+    fn cell_count(&self) -> usize {
+        match self {
+            Pattern::Var(..) | Pattern::Lit(..) => 0,
+            Pattern::Cell { args, .. } => 1 + args.iter().map(Pattern::cell_count).sum::<usize>()
+        }
+    }
+
+    fn lit_pos(&self) -> Option<Pos> {
+        match self {
+            Pattern::Var(..) => None,
+            Pattern::Lit(_, pos) => Some(*pos),
+            Pattern::Cell { args, .. } => args.iter().find_map(Pattern::lit_pos)
+        }
+    }
+
+    fn lit_count(&self) -> usize {
+        match self {
+            Pattern::Var(..) => 0,
+            Pattern::Lit(..) => 1,
+            Pattern::Cell { args, .. } => args.iter().map(Pattern::lit_count).sum()
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Guard {
+    pub name: String,
+    pub args: Vec<String>
+}
+
+#[derive(Clone, Debug)]
+pub struct Rule {
+    pub lhs: Pattern,
+    pub rhs: Pattern,
+    pub guards: Vec<Guard>,
+    pub pos: Pos
+}
+
+/// The rules of a `.rules` file, checked against the known cell types.
+#[derive(Clone, Debug, Default)]
+pub struct RuleFile {
+    pub rules: Vec<Rule>
+}
+
+// auxiliary functions
+fn tokenize(src: &str) -> Vec<(String, Pos)> {
+    let mut tokens = Vec::new();
+    let mut symbol: Option<(String, Pos)> = None;
+    let mut in_comment = false;
+    for (line, text) in src.lines().enumerate() {
+        for (col, c) in text.chars().enumerate() {
+            let pos = Pos { line: line + 1, col: col + 1 };
+            if in_comment {
+                break;
+            }
+            match c {
+                '(' | ')' | ';' => {
+                    tokens.extend(symbol.take());
+                    match c {
+                        ';' => in_comment = true,
+                        _ => tokens.push((c.to_string(), pos))
+                    }
+                },
+                c if c.is_whitespace() => tokens.extend(symbol.take()),
+                c => symbol.get_or_insert_with(|| (String::new(), pos)).0.push(c)
+            }
+        }
+        tokens.extend(symbol.take());
+        in_comment = false;
+    }
+    tokens
+}
+
+fn parse_sexp(tokens: &[(String, Pos)], i: &mut usize) -> Result<Sexp, RuleError> {
+    let (token, pos) = &tokens[*i];
+    *i += 1;
+    match token.as_str() {
+        "(" => {
+            let mut items = Vec::new();
+            loop {
+                match tokens.get(*i) {
+                    None => return Err(RuleError::Parse { pos: *pos, msg: "unclosed `(`".to_string() }),
+                    Some((t, _)) if t == ")" => {
+                        *i += 1;
+                        return Ok(Sexp::List(items, *pos));
+                    },
+                    Some(_) => items.push(parse_sexp(tokens, i)?)
+                }
+            }
+        },
+        ")" => Err(RuleError::Parse { pos: *pos, msg: "unexpected `)`".to_string() }),
+        _ => Ok(Sexp::Symbol(token.clone(), *pos))
+    }
+}
+
+fn op_arity(op: &str) -> Option<usize> {
+    if NetlistDatabase::AY_TYPES.contains(&op) {
+        Some(1)
+    }
+    else if NetlistDatabase::ABY_TYPES.contains(&op) {
+        Some(2)
+    }
+    else if NetlistDatabase::ABSY_TYPES.contains(&op) {
+        Some(3)
+    }
+    else {
+        None
+    }
+}
+
+fn parse_pattern(sexp: &Sexp) -> Result<Pattern, RuleError> {
+    match sexp {
+        Sexp::Symbol(name, pos) if name.parse::<i64>().is_ok() => match name.parse().unwrap() {
+            // constant words are indexed by their unsigned value, so a negative literal never matches
+            value if value < 0 => Err(RuleError::Parse { pos: *pos, msg: format!("negative literal `{}`, write the word as unsigned", name) }),
+            value => Ok(Pattern::Lit(value, *pos))
+        },
+        Sexp::Symbol(name, pos) => {
+            let valid = name.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+            match valid {
+                true => Ok(Pattern::Var(name.clone(), *pos)),
+                false => Err(RuleError::Parse { pos: *pos, msg: format!("invalid variable name `{}`", name) })
+            }
+        },
+        Sexp::List(items, pos) => {
+            let Some(Sexp::Symbol(op, op_pos)) = items.first() else {
+                return Err(RuleError::Parse { pos: *pos, msg: "expected a cell type after `(`".to_string() });
+            };
+            let expected = op_arity(op).ok_or_else(|| RuleError::UnknownOp { pos: *op_pos, op: op.clone() })?;
+            if items.len() - 1 != expected {
+                return Err(RuleError::Arity { pos: *pos, op: op.clone(), expected, found: items.len() - 1 });
+            }
+            let args = items[1..].iter().map(parse_pattern).collect::<Result<_, _>>()?;
+            Ok(Pattern::Cell { op: op.clone(), args, pos: *pos })
+        }
+    }
+}
+
+fn parse_guards(sexp: &Sexp) -> Result<Vec<Guard>, RuleError> {
+    let Sexp::List(items, _) = sexp else {
+        return Err(RuleError::Parse { pos: sexp.pos(), msg: "expected a list of guards after `:when`".to_string() });
+    };
+    let mut guards = Vec::new();
+    for item in items {
+        let Sexp::List(parts, pos) = item else {
+            return Err(RuleError::Parse { pos: item.pos(), msg: "expected a guard such as `(is-bit a)`".to_string() });
+        };
+        let Some(Sexp::Symbol(name, name_pos)) = parts.first() else {
+            return Err(RuleError::Parse { pos: *pos, msg: "expected a guard name after `(`".to_string() });
+        };
+        let &(_, expected) = GUARDS.iter()
+            .find(|(g, _)| *g == name.as_str())
+            .ok_or_else(|| RuleError::UnknownGuard { pos: *name_pos, guard: name.clone() })?;
+        if parts.len() - 1 != expected {
+            return Err(RuleError::Arity { pos: *pos, op: name.clone(), expected, found: parts.len() - 1 });
+        }
+        let mut args = Vec::new();
+        for part in parts[1..].iter() {
+            match parse_pattern(part)? {
+                Pattern::Var(var, _) => args.push(var),
+                Pattern::Lit(_, pos) | Pattern::Cell { pos, .. } => return Err(RuleError::Parse { pos, msg: "guard operands must be variables".to_string() })
+            }
+        }
+        guards.push(Guard { name: name.clone(), args });
+    }
+    Ok(guards)
+}
+
+/// Checks that the rule can be applied left to right: the left-hand side is a cell, and
+/// every variable of the right-hand side and of the guards is bound by it.
+fn check_rule(rule: &Rule) -> Result<(), RuleError> {
+    if let Pattern::Var(_, pos) | Pattern::Lit(_, pos) = rule.lhs {
+        return Err(RuleError::BareLhs { pos });
+    }
+    if let Some(pos) = rule.rhs.lit_pos() {
+        return Err(RuleError::Parse { pos, msg: "literals may only appear on the left-hand side".to_string() });
+    }
+    let mut lhs_vars = Vec::new();
+    rule.lhs.vars(&mut lhs_vars);
+    let bound: HashSet<&str> = lhs_vars.into_iter().map(|(v, _)| v).collect();
+    let mut rhs_vars = Vec::new();
+    rule.rhs.vars(&mut rhs_vars);
+    rhs_vars.extend(rule.guards.iter().flat_map(|g| g.args.iter().map(|a| (a.as_str(), rule.pos))));
+    match rhs_vars.into_iter().find(|(v, _)| !bound.contains(v)) {
+        Some((var, pos)) => Err(RuleError::UnboundVar { pos, var: var.to_string() }),
+        None => Ok(())
+    }
+}
+
+impl RuleFile {
+    pub fn parse(src: &str) -> Result<Self, RuleError> {
+        let tokens = tokenize(src);
+        let mut rules = Vec::new();
+        let mut i = 0;
+        while i < tokens.len() {
+            let form = parse_sexp(&tokens, &mut i)?;
+            let bad_form = || RuleError::Parse {
+                pos: form.pos(),
+                msg: "expected `(rewrite lhs rhs)` or `(birewrite lhs rhs)`, optionally followed by `:when (guards)`".to_string()
+            };
+            let Sexp::List(items, pos) = &form else {
+                return Err(bad_form());
+            };
+            let both_ways = match items.first() {
+                Some(Sexp::Symbol(s, _)) if s == "rewrite" => false,
+                Some(Sexp::Symbol(s, _)) if s == "birewrite" => true,
+                _ => return Err(bad_form())
+            };
+            let guards = match &items[1..] {
+                [_, _] => Vec::new(),
+                [_, _, Sexp::Symbol(kw, _), guards] if kw == ":when" => parse_guards(guards)?,
+                _ => return Err(bad_form())
+            };
+            let (lhs, rhs) = (parse_pattern(&items[1])?, parse_pattern(&items[2])?);
+            if both_ways {
+                let rule = Rule { lhs: rhs.clone(), rhs: lhs.clone(), guards: guards.clone(), pos: *pos };
+                check_rule(&rule)?;
+                rules.push(rule);
+            }
+            let rule = Rule { lhs, rhs, guards, pos: *pos };
+            check_rule(&rule)?;
+            rules.push(rule);
+        }
+        Ok(Self { rules })
+    }
+
+    pub fn load(path: &str) -> Result<Self, RuleError> {
+        let src = std::fs::read_to_string(path)
+            .map_err(|source| RuleError::Io { path: path.to_string(), source })?;
+        Self::parse(&src)
+    }
+}
+
+/// What the actions of a compiled rule need from the database.
+struct RhsContext<'a> {
+    funcs: &'a ExternalFuncs,
+    types: &'a BiHashMap<String, Value>,
+    tables: [TableId; 3],   // by number of operands
+    next_ts: Value
+}
+
+impl RhsContext<'_> {
+    fn cell(&self, op: &str) -> (Value, TableId) {
+        let ty = *self.types.get_by_left(op).expect("Unknown cell type");
+        (ty, self.tables[op_arity(op).expect("Unknown cell type") - 1])
+    }
+
+    /// Inserts the cells of `pat` and returns the wirevec it stands for; nested cells get fresh outputs as wide as `root`.
+    fn emit(&self, rb: &mut RuleBuilder, pat: &Pattern, vars: &Bindings, out: Option<Variable>, root: Variable) -> Variable {
+        match pat {
+            Pattern::Var(name, _) => vars[name.as_str()],
+            Pattern::Lit(..) => unreachable!("literals are rejected on the right-hand side"),
+            Pattern::Cell { op, args, .. } => {
+                let (ty, table) = self.cell(op);
+                let ins: Vec<Variable> = args.iter().map(|a| self.emit(rb, a, vars, None, root)).collect();
+                let out = match out {
+                    Some(out) => out,
+                    None => {
+                        let key: Vec<QueryEntry> = std::iter::once(ty.into())
+                            .chain(ins.iter().map(|v| (*v).into()))
+                            .chain(std::iter::once(root.into()))
+                            .collect();
+                        rb.call_external(self.funcs.fresh_for, &key).unwrap()
+                    }
+                };
+                let row: Vec<QueryEntry> = std::iter::once(ty.into())
+                    .chain(ins.iter().map(|v| (*v).into()))
+                    .chain([out.into(), self.next_ts.into()])
+                    .collect();
+                rb.insert(table, &row).unwrap();
+                out
+            }
+        }
+    }
+}
+
+impl NetlistDatabase {
+    /// Appends the atoms of `pat` to `atoms`, naming the output of the i-th cell (in pre-order) `names.0[i]`
+    /// and the i-th literal `names.1[i]`.
+    fn lhs_atoms<'a>(&self, pat: &'a Pattern, names: &'a (Vec<String>, Vec<String>), next: &mut (usize, usize), atoms: &mut Vec<PatAtom<'a>>) -> Term<'a> {
+        match pat {
+            Pattern::Var(name, _) => Term::Var(name),
+            Pattern::Lit(value, _) => {
+                let vec = names.1[next.1].as_str();
+                next.1 += 1;
+                atoms.push(atom(self.const_words, &[Term::Var(vec), Term::Const(self.db.base_values().get::<i64>(*value))]));
+                Term::Var(vec)
+            },
+            Pattern::Cell { op, args, .. } => {
+                let out = names.0[next.0].as_str();
+                next.0 += 1;
+                let mut terms = vec![Term::Const(*self.types.get_by_left(op).expect("Unknown cell type"))];
+                for arg in args {
+                    terms.push(self.lhs_atoms(arg, names, next, atoms));
+                }
+                terms.push(Term::Var(out));
+                let table = [self.ay_cells, self.aby_cells, self.absy_cells][args.len() - 1];
+                atoms.push(atom(table, &terms));
+                Term::Var(out)
+            }
+        }
+    }
+
+    /// Runs every rule of `rules` once, like the built-in `rewrite_*_all` rule sets.
+    /// Returns whether any rule changed the database.
+    pub fn rewrite_file_all(&mut self, rules: &RuleFile, strategy: PlanStrategy, recent_range: Range<Value>) -> bool {
+        // output names of the left-hand side cells, which cannot clash with rule variables
+        let names: Vec<(Vec<String>, Vec<String>)> = rules.rules.iter()
+            .map(|r| (
+                (0..r.lhs.cell_count()).map(|i| format!("?{}", i)).collect(),
+                (0..r.lhs.lit_count()).map(|i| format!("?k{}", i)).collect()
+            ))
+            .collect();
+        let mut patterns = Vec::new();
+        for (rule, names) in rules.rules.iter().zip(names.iter()) {
+            let mut atoms = Vec::new();
+            self.lhs_atoms(&rule.lhs, names, &mut (0, 0), &mut atoms);
+            patterns.push(atoms);
+        }
+
+        let ctx = RhsContext {
+            funcs: &self.funcs,
+            types: &self.types,
+            tables: [self.ay_cells, self.aby_cells, self.absy_cells],
+            next_ts: recent_range.end
+        };
+        let mut rsb = self.db.new_rule_set();
+
+        for ((rule, names), atoms) in rules.rules.iter().zip(names.iter()).zip(patterns.iter()) {
+            add_seminaive_rule(&mut rsb, strategy, &recent_range, atoms, |rb, vars| {
+                let root = vars[names.0[0].as_str()];
+                for name in names.0[1..].iter() {
+                    rb.call_external(ctx.funcs.width_ge, &[vars[name.as_str()].into(), root.into()]).unwrap();
+                }
+                for guard in rule.guards.iter() {
+                    let func = match guard.name.as_str() {
+                        "width-ge" => ctx.funcs.width_ge,
+                        "width-gt" => ctx.funcs.width_gt,
+                        "width-eq" => ctx.funcs.width_eq,
+                        "is-bit" => ctx.funcs.is_bit,
+                        "is-zero" => ctx.funcs.is_zero,
+                        "msb-zero" => ctx.funcs.msb_zero,
+                        _ => ctx.funcs.is_const
+                    };
+                    let args: Vec<QueryEntry> = guard.args.iter().map(|a| vars[a.as_str()].into()).collect();
+                    rb.call_external(func, &args).unwrap();
+                }
+                match &rule.rhs {
+                    Pattern::Var(name, _) => {
+                        rb.call_external(ctx.funcs.union_vecs, &[vars[name.as_str()].into(), root.into()]).unwrap();
+                    },
+                    cell => {
+                        ctx.emit(rb, cell, vars, Some(root), root);
+                    }
+                }
+            });
+        }
+
+        // finish and run the rule set
+        let rs = rsb.build();
+        self.db.run_rule_set(&rs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_err(src: &str) -> RuleError {
+        RuleFile::parse(src).expect_err("the rule file should not parse")
+    }
+
+    #[test]
+    fn parses_rewrites_guards_and_birewrites() {
+        let rules = RuleFile::parse("
+            ; a comment ( with parentheses
+            (rewrite ($addu ($addu a b) c) ($addu a ($addu b c)))
+            (birewrite ($mulu a b) ($mulu b a))
+            (rewrite ($shlu a k) a :when ((is-zero k)))
+            (rewrite ($mux a b 1) b)
+        ").unwrap();
+        assert_eq!(rules.rules.len(), 5);
+        assert_eq!(rules.rules[0].pos, Pos { line: 3, col: 13 });
+        assert_eq!(rules.rules[3].guards.len(), 1);
+        assert!(matches!(rules.rules[4].lhs, Pattern::Cell { ref args, .. } if matches!(args[2], Pattern::Lit(1, _))));
+    }
+
+    #[test]
+    fn parses_the_bundled_rules() {
+        let rules = RuleFile::load(concat!(env!("CARGO_MANIFEST_DIR"), "/rules/basic.rules")).unwrap();
+        assert!(!rules.rules.is_empty());
+    }
+
+    #[test]
+    fn rejects_unbalanced_parentheses() {
+        assert!(matches!(parse_err("(rewrite ($addu a b) ($addu b a)"), RuleError::Parse { pos: Pos { line: 1, col: 1 }, .. }));
+        assert!(matches!(parse_err("(rewrite ($addu a b) ($addu b a)))"), RuleError::Parse { pos: Pos { line: 1, col: 34 }, .. }));
+    }
+
+    #[test]
+    fn rejects_malformed_forms() {
+        assert!(matches!(parse_err("rewrite"), RuleError::Parse { .. }));
+        assert!(matches!(parse_err("(rule ($addu a b) ($addu b a))"), RuleError::Parse { .. }));
+        assert!(matches!(parse_err("(rewrite ($addu a b))"), RuleError::Parse { .. }));
+        assert!(matches!(parse_err("(rewrite ($addu a b) a :if ((is-bit a)))"), RuleError::Parse { .. }));
+        assert!(matches!(parse_err("(rewrite (a b) a)"), RuleError::UnknownOp { .. }));
+        assert!(matches!(parse_err("(rewrite (() b) a)"), RuleError::Parse { .. }));
+        assert!(matches!(parse_err("(rewrite ($addu a b-c) a)"), RuleError::Parse { .. }));
+    }
+
+    #[test]
+    fn rejects_unknown_cell_types_and_wrong_arities() {
+        match parse_err("(rewrite ($addu a b) ($frob a b))") {
+            RuleError::UnknownOp { pos, op } => assert_eq!((pos, op.as_str()), (Pos { line: 1, col: 23 }, "$frob")),
+            err => panic!("unexpected error {}", err)
+        }
+        // cell types carry their signedness
+        assert!(matches!(parse_err("(rewrite ($add a b) ($add b a))"), RuleError::UnknownOp { .. }));
+        match parse_err("(rewrite ($addu a) a)") {
+            RuleError::Arity { expected, found, .. } => assert_eq!((expected, found), (2, 1)),
+            err => panic!("unexpected error {}", err)
+        }
+        assert!(matches!(parse_err("(rewrite ($mux a b) a)"), RuleError::Arity { expected: 3, found: 2, .. }));
+    }
+
+    #[test]
+    fn rejects_bad_guards() {
+        assert!(matches!(parse_err("(rewrite ($shlu a k) a :when ((is-one k)))"), RuleError::UnknownGuard { .. }));
+        assert!(matches!(parse_err("(rewrite ($shlu a k) a :when ((is-zero k a)))"), RuleError::Arity { expected: 1, found: 2, .. }));
+        assert!(matches!(parse_err("(rewrite ($shlu a k) a :when (is-zero k))"), RuleError::Parse { .. }));
+        assert!(matches!(parse_err("(rewrite ($shlu a k) a :when ((is-zero 0)))"), RuleError::Parse { .. }));
+        assert!(matches!(parse_err("(rewrite ($shlu a k) a :when ((is-zero j)))"), RuleError::UnboundVar { .. }));
+    }
+
+    #[test]
+    fn rejects_rules_that_cannot_be_applied() {
+        match parse_err("(rewrite ($addu a b) ($addu a c))") {
+            RuleError::UnboundVar { pos, var } => assert_eq!((pos, var.as_str()), (Pos { line: 1, col: 31 }, "c")),
+            err => panic!("unexpected error {}", err)
+        }
+        assert!(matches!(parse_err("(rewrite a ($posu a))"), RuleError::BareLhs { .. }));
+        // a birewrite is checked in both directions
+        assert!(matches!(parse_err("(birewrite ($posu a) a)"), RuleError::BareLhs { .. }));
+        assert!(matches!(parse_err("(rewrite ($addu a 0) 0)"), RuleError::Parse { .. }));
+    }
+
+    #[test]
+    fn rejects_negative_literals() {
+        match parse_err("(rewrite ($addu a -1) ($subu a 1))") {
+            RuleError::Parse { pos, .. } => assert_eq!(pos, Pos { line: 1, col: 19 }),
+            err => panic!("unexpected error {}", err)
+        }
+    }
+
+    #[test]
+    fn reports_positions_across_lines() {
+        match parse_err("(rewrite ($addu a b) ($addu b a))\n\n  (rewrite ($subu a b) ($addu a))") {
+            RuleError::Arity { pos, .. } => assert_eq!(pos, Pos { line: 3, col: 24 }),
+            err => panic!("unexpected error {}", err)
+        }
+    }
+}
@@ -0,0 +1,243 @@
+use core_relations::{PlanStrategy, Value};
+use numeric_id::NumericId;
+use std::time::{Duration, Instant};
+use log::{debug, info};
+use crate::core::NetlistDatabase;
+use crate::progress::Progress;
+use crate::rules::RuleFile;
+
+
+/// The built-in rule sets that `saturate` can run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RuleSet {
+    Basic,      // associativity and commutativity
+    Shift,      // multiplications by powers of two and shift composition
+    Unary,      // negation and reductions
+    Register,   // enables and synchronous resets as muxes in front of a plain register
+    Fold        // constant folding, constant mux selects and identity operands
+}
+
+impl RuleSet {
+    pub const ALL: &[RuleSet] = &[RuleSet::Basic, RuleSet::Shift, RuleSet::Unary, RuleSet::Register, RuleSet::Fold];
+
+    /// Looks up a built-in rule set by its lowercase name, e.g. `basic`.
+    pub fn from_name(name: &str) -> Option<RuleSet> {
+        match name {
+            "basic" => Some(RuleSet::Basic),
+            "shift" => Some(RuleSet::Shift),
+            "unary" => Some(RuleSet::Unary),
+            "register" => Some(RuleSet::Register),
+            "fold" => Some(RuleSet::Fold),
+            _ => None
+        }
+    }
+}
+
+/// Limits and rules of an equality saturation run.
+#[derive(Clone)]
+pub struct RunConfig {
+    pub rule_sets: Vec<RuleSet>,
+    pub rule_files: Vec<RuleFile>,  // run after the built-in rule sets
+    pub strategy: PlanStrategy,
+    pub iter_limit: usize,
+    pub node_limit: usize,  // total number of rows in the cell tables
+    pub time_limit: Duration
+}
+
+impl Default for RunConfig {
+    fn default() -> Self {
+        Self {
+            rule_sets: RuleSet::ALL.to_vec(),
+            rule_files: Vec::new(),
+            strategy: PlanStrategy::PureSize,
+            iter_limit: 10,
+            node_limit: 1_000_000,
+            time_limit: Duration::from_secs(60)
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    Saturated,
+    IterationLimit,
+    NodeLimit,
+    TimeLimit
+}
+
+/// What a `saturate` run did and why it stopped.
+#[derive(Clone, Debug)]
+pub struct RunReport {
+    pub stop_reason: StopReason,
+    pub iterations: usize,
+    pub rebuilds: usize,    // rounds of the congruence loop over all iterations
+    pub nodes: usize,
+    pub unions: usize,
+    pub elapsed: Duration
+}
+
+impl NetlistDatabase {
+    /// Number of rows in each cell table and in the union-find.
+    fn table_sizes(&self) -> Vec<usize> {
+        [self.ay_cells, self.aby_cells, self.absy_cells, self.dffs, self.opaque_cells, self.displaced].iter()
+            .map(|t| self.db.get_table(*t).len())
+            .collect()
+    }
+
+    /// Merges pending writes, then canonicalizes containers and tables until nothing changes.
+    /// Returns the number of rounds that changed something.
+    ///
+    /// A container may keep its id when its wires are canonicalized, so once containers changed
+    /// the next `index_const_words` checks every wirevec again.
+    fn rebuild(&mut self) -> usize {
+        let tables = [self.ay_cells, self.aby_cells, self.absy_cells, self.dffs, self.opaque_cells, self.const_words];
+        let mut rounds = 0;
+        loop {
+            self.db.merge_all();
+            let ts = Value::from_usize(self.db.read_counter(self.ts_counter));
+            let container_modified = self.db.rebuild_containers(self.displaced);
+            if container_modified {
+                self.const_words_from = 0;
+            }
+            let table_modified = self.db.apply_rebuild(self.displaced, &tables, ts);
+            if !container_modified && !table_modified {
+                break;  // no more changes
+            }
+            rounds += 1;
+        }
+        rounds
+    }
+
+    /// Runs the configured rule sets until no rule adds anything new or a limit is hit.
+    /// Each iteration matches the rows of the previous one as recent, stamps its own
+    /// rows with the next timestamp and restores congruence before the next iteration.
+    ///
+    /// The time limit is checked before each rule set, so a run may overshoot it by the
+    /// time one rule set and the rebuild after it take.
+    pub fn saturate(&mut self, config: &RunConfig) -> RunReport {
+        let start = Instant::now();
+        let mut rebuilds = self.rebuild();
+        let mut recent_start = Value::new(0);
+        let mut iterations = 0;
+
+        let stop_reason = loop {
+            if iterations >= config.iter_limit {
+                break StopReason::IterationLimit;
+            }
+            if start.elapsed() >= config.time_limit {
+                break StopReason::TimeLimit;
+            }
+
+            // rows stamped in [recent_start, recent_end) are new since the previous iteration
+            self.db.inc_counter(self.ts_counter);
+            let recent_end = Value::from_usize(self.db.read_counter(self.ts_counter));
+            let recent_range = recent_start..recent_end;
+            let before = self.table_sizes();
+            let mut changed = false;
+            let mut timed_out = false;
+            for rule_set in config.rule_sets.iter() {
+                if start.elapsed() >= config.time_limit {
+                    timed_out = true;
+                    break;
+                }
+                changed |= match rule_set {
+                    RuleSet::Basic => self.rewrite_basic_all(config.strategy, recent_range.clone()),
+                    RuleSet::Shift => self.rewrite_shift_all(config.strategy, recent_range.clone()),
+                    RuleSet::Unary => self.rewrite_unary_all(config.strategy, recent_range.clone()),
+                    RuleSet::Register => self.rewrite_register_all(config.strategy, recent_range.clone()),
+                    RuleSet::Fold => self.rewrite_fold_all(config.strategy, recent_range.clone())
+                };
+            }
+            for rules in config.rule_files.iter() {
+                if timed_out || start.elapsed() >= config.time_limit {
+                    timed_out = true;
+                    break;
+                }
+                changed |= self.rewrite_file_all(rules, config.strategy, recent_range.clone());
+            }
+            let rounds = self.rebuild();
+            rebuilds += rounds;
+            self.index_const_words();  // constants made by this iteration
+            iterations += 1;
+            recent_start = recent_end;
+
+            let after = self.table_sizes();
+            let rows_added = after[..5].iter().sum::<usize>().saturating_sub(before[..5].iter().sum());
+            let unions = after[5].saturating_sub(before[5]);
+            debug!("Iteration {}: {} row(s) added, {} union(s), {} rebuild round(s)", iterations, rows_added, unions, rounds);
+            self.report(Progress::Iteration {
+                iteration: iterations,
+                rows_added,
+                unions,
+                rebuilds: rounds,
+                rows: after[..5].iter().sum(),
+                elapsed_ms: start.elapsed().as_millis()
+            });
+            if timed_out {
+                break StopReason::TimeLimit;
+            }
+            if !changed && rounds == 0 {
+                break StopReason::Saturated;
+            }
+            if after[..5].iter().sum::<usize>() > config.node_limit {
+                break StopReason::NodeLimit;
+            }
+        };
+
+        let sizes = self.table_sizes();
+        let report = RunReport {
+            stop_reason,
+            iterations,
+            rebuilds,
+            nodes: sizes[..5].iter().sum(),
+            unions: sizes[5],
+            elapsed: start.elapsed()
+        };
+        info!("Saturation stopped after {} iteration(s) ({:?}) with {} rows", report.iterations, report.stop_reason, report.nodes);
+        self.report(Progress::Saturated {
+            stop_reason: format!("{:?}", report.stop_reason),
+            iterations: report.iterations,
+            rows: report.nodes,
+            unions: report.unions,
+            elapsed_ms: report.elapsed.as_millis()
+        });
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hierarchy::Hierarchy;
+    use crate::yosys;
+    use serde_json::json;
+
+    #[test]
+    fn rebuild_merges_constant_words_made_equal_by_unions() {
+        // y = 1 << 0, so y becomes the constant word of a, next to b and the empty wirevec
+        let netlist: yosys::Netlist = serde_json::from_value(json!({
+            "modules": {
+                "top": {
+                    "ports": {
+                        "y": { "direction": "output", "bits": [2, 3] }
+                    },
+                    "cells": {
+                        "shl": {
+                            "type": "$shl",
+                            "parameters": { "A_SIGNED": 0, "B_SIGNED": 0, "A_WIDTH": 2, "B_WIDTH": 1, "Y_WIDTH": 2 },
+                            "connections": { "A": ["1", "0"], "B": ["0"], "Y": [2, 3] }
+                        }
+                    }
+                }
+            }
+        })).unwrap();
+        let mut db = NetlistDatabase::default();
+        db.build_mod("top", &netlist.modules, &[], Hierarchy::Flatten).unwrap();
+        assert_eq!(db.db.get_table(db.const_words).len(), 3);
+
+        let report = db.saturate(&RunConfig { rule_sets: vec![RuleSet::Shift], ..RunConfig::default() });
+        assert_eq!(report.stop_reason, StopReason::Saturated);
+        assert!(report.unions > 0);
+        assert_eq!(db.db.get_table(db.const_words).len(), 3);
+    }
+}
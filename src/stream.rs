@@ -0,0 +1,261 @@
+use serde::de::{self, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, Visitor};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    fs::File,
+    io::{self, BufReader, Read},
+    rc::Rc,
+    time::Instant
+};
+use log::{info, trace};
+use crate::core::NetlistDatabase;
+use crate::error::{Location, NetlistError};
+use crate::hierarchy::Hierarchy;
+use crate::progress::Progress;
+use crate::yosys;
+
+
+/// Counts the bytes read from the netlist file, to report how far the import got.
+struct CountingReader<R> {
+    inner: R,
+    count: Rc<std::cell::Cell<u64>>
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count.set(self.count.get() + n as u64);
+        Ok(n)
+    }
+}
+
+/// State of a streaming import. Cells of the top module go into the tables as soon as they are
+/// parsed; only the other modules (which instances may refer to) and the cells that need them are kept.
+struct StreamImport<'a> {
+    netlist: &'a mut NetlistDatabase,
+    top_mod: &'a str,
+    clk_names: &'a [&'a str],
+    found_top: bool,
+    ports_done: bool,
+    early: Vec<(String, yosys::Cell)>,  // cells before the ports, which Yosys never writes
+    others: Vec<(String, yosys::Cell)>, // instances and cells of unknown types, imported at the end
+    netnames: BTreeMap<String, yosys::Netname>,
+    modules: BTreeMap<String, yosys::Module>,
+    cells_done: usize,
+    bytes_read: Rc<std::cell::Cell<u64>>,
+    file_len: u64,
+    percent: u64,   // last reported
+    start: Instant,
+    error: Option<NetlistError>
+}
+
+impl StreamImport<'_> {
+    /// Keeps an import error for after parsing, handing serde an error that stops it.
+    fn fail<E: de::Error>(&mut self, err: NetlistError) -> E {
+        let de_err = E::custom(&err);
+        self.error = Some(err);
+        de_err
+    }
+
+    fn ports(&mut self, ports: &BTreeMap<String, yosys::Port>) -> Result<(), NetlistError> {
+        self.netlist.build_ports(self.top_mod, ports, self.clk_names)?;
+        self.ports_done = true;
+        for (name, cell) in std::mem::take(&mut self.early) {
+            self.cell(name, cell)?;
+        }
+        Ok(())
+    }
+
+    fn cell(&mut self, name: String, cell: yosys::Cell) -> Result<(), NetlistError> {
+        if !self.ports_done {
+            self.early.push((name, cell));
+            return Ok(());
+        }
+        trace!("Processing cell {}: {}", self.cells_done, name);
+        let loc = Location::cell(self.top_mod, &name, cell.src());
+        if self.netlist.build_cell(&cell, &loc)?.is_some() {
+            self.others.push((name, cell));
+        }
+        self.cells_done += 1;
+
+        let percent = 100 * self.bytes_read.get() / self.file_len.max(1);
+        if percent > self.percent {
+            self.percent = percent;
+            self.netlist.report(Progress::Import {
+                module: self.top_mod.to_string(),
+                cells_done: self.cells_done,
+                cells_total: None,
+                percent: percent as f64,
+                cells_per_sec: NetlistDatabase::throughput(self.cells_done, self.start)
+            });
+        }
+        Ok(())
+    }
+}
+
+// one seed per level of the netlist, each visiting the object at that level
+struct NetlistSeed<'s, 'a>(&'s mut StreamImport<'a>);
+struct ModulesSeed<'s, 'a>(&'s mut StreamImport<'a>);
+struct TopSeed<'s, 'a>(&'s mut StreamImport<'a>);
+struct CellsSeed<'s, 'a>(&'s mut StreamImport<'a>);
+
+impl<'de> DeserializeSeed<'de> for NetlistSeed<'_, '_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for NetlistSeed<'_, '_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a Yosys JSON netlist")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "modules" => map.next_value_seed(ModulesSeed(&mut *self.0))?,
+                _ => { map.next_value::<IgnoredAny>()?; }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for ModulesSeed<'_, '_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for ModulesSeed<'_, '_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a map of modules")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        while let Some(name) = map.next_key::<String>()? {
+            match name == self.0.top_mod {
+                true => {
+                    map.next_value_seed(TopSeed(&mut *self.0))?;
+                    self.0.found_top = true;
+                },
+                false => {
+                    let module: yosys::Module = map.next_value()?;
+                    self.0.modules.insert(name, module);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for TopSeed<'_, '_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for TopSeed<'_, '_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a module")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "ports" => {
+                    let ports: BTreeMap<String, yosys::Port> = map.next_value()?;
+                    self.0.ports(&ports).map_err(|err| self.0.fail::<A::Error>(err))?;
+                },
+                "cells" => map.next_value_seed(CellsSeed(&mut *self.0))?,
+                "netnames" => self.0.netnames = map.next_value()?,
+                _ => { map.next_value::<IgnoredAny>()?; }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for CellsSeed<'_, '_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for CellsSeed<'_, '_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a map of cells")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        while let Some(name) = map.next_key::<String>()? {
+            let cell: yosys::Cell = map.next_value()?;
+            self.0.cell(name, cell).map_err(|err| self.0.fail::<A::Error>(err))?;
+        }
+        Ok(())
+    }
+}
+
+impl NetlistDatabase {
+    /// Imports `top_mod` from a Yosys JSON netlist. Registers may be clocked by any of the named
+    /// input ports; with no names, every single-bit net driving a register clock becomes a clock.
+    /// A clock derived from another one by logic, like a gated clock, is a domain of its own:
+    /// the clock enable it implies is not recovered. Instances of other modules of the netlist
+    /// are inlined or kept opaque according to `hierarchy`.
+    ///
+    /// The file is streamed: cells of `top_mod` are imported one by one while parsing, so the
+    /// netlist is never held in memory as a whole. `build_mod` imports a netlist parsed up front.
+    pub fn build_from_json(&mut self, json_path: &str, top_mod: &str, clk_names: &[&str], hierarchy: Hierarchy) -> Result<(), NetlistError> {
+        let start = Instant::now();
+        let io_err = |source| NetlistError::Io { path: json_path.to_string(), source };
+        let file = File::open(json_path).map_err(io_err)?;
+        let file_len = file.metadata().map_err(io_err)?.len();
+        let bytes_read = Rc::new(std::cell::Cell::new(0));
+        let reader = BufReader::new(CountingReader { inner: file, count: bytes_read.clone() });
+
+        let mut import = StreamImport {
+            netlist: self, top_mod, clk_names,
+            found_top: false, ports_done: false,
+            early: Vec::new(), others: Vec::new(), netnames: BTreeMap::new(), modules: BTreeMap::new(),
+            cells_done: 0, bytes_read, file_len, percent: 0, start, error: None
+        };
+        let mut deserializer = serde_json::Deserializer::from_reader(reader);
+        let parsed = NetlistSeed(&mut import).deserialize(&mut deserializer).and_then(|_| deserializer.end());
+        if let Err(source) = parsed {
+            return Err(import.error.take().unwrap_or_else(|| NetlistError::Json { path: json_path.to_string(), source }));
+        }
+        info!("Streamed JSON netlist from {}", json_path);
+        if !import.found_top {
+            return Err(NetlistError::MissingModule { module: top_mod.to_string() });
+        }
+
+        // a module without ports
+        if !import.ports_done {
+            import.ports(&BTreeMap::new())?;
+        }
+        let StreamImport { netlist, cells_done, others, netnames, modules, .. } = import;
+        for (name, netname) in netnames.iter() {
+            netlist.build_netname(top_mod, name, netname)?;
+        }
+        let others: Vec<(&str, &yosys::Cell)> = others.iter().map(|(n, c)| (n.as_str(), c)).collect();
+        netlist.build_instances(top_mod, &others, &modules, hierarchy)?;
+        netlist.finish_mod(top_mod, cells_done, start);
+        Ok(())
+    }
+}
@@ -0,0 +1,273 @@
+use std::{collections::BTreeSet, fmt::Write};
+use crate::core::{CellRows, NetlistDatabase};
+use crate::error::VerilogError;
+use crate::export::{Bit, BitMap, ExportCell, ExportNetName, ExportPort};
+use crate::param::ParamBits;
+
+
+impl NetlistDatabase {
+    pub(crate) const VERILOG_AY_OPS: &[(&str, &str)] = &[
+        ("$not", "~"), ("$neg", "-"), ("$pos", "+"), ("$logic_not", "!"),
+        ("$reduce_and", "&"), ("$reduce_or", "|"), ("$reduce_xor", "^"), ("$reduce_xnor", "~^"), ("$reduce_bool", "|")
+    ];
+    pub(crate) const VERILOG_ABY_OPS: &[(&str, &str)] = &[
+        ("$and", "&"), ("$or", "|"), ("$xor", "^"),
+        ("$eq", "=="), ("$ne", "!="), ("$ge", ">="), ("$le", "<="), ("$gt", ">"), ("$lt", "<"),
+        ("$logic_and", "&&"), ("$logic_or", "||"),
+        ("$add", "+"), ("$sub", "-"), ("$mul", "*"), ("$div", "/"), ("$mod", "%"),
+        ("$shl", "<<"), ("$shr", ">>"), ("$sshl", "<<<"), ("$sshr", ">>>"), ("$shift", ">>"), ("$shiftx", ">>")   // x-fill of $shiftx is a don't-care
+    ];
+    pub(crate) const VERILOG_ABY_NEG_OPS: &[(&str, &str)] = &[("$nand", "&"), ("$nor", "|"), ("$xnor", "^")];
+
+    // auxiliary functions
+    fn verilog_ident(name: &str) -> String {
+        let simple = name.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$');
+        match simple {
+            true => name.to_string(),
+            false => format!("\\{} ", name)
+        }
+    }
+
+    /// A string literal with Verilog escapes, bytes outside printable ASCII as octal (`\ooo`).
+    fn verilog_string(s: &str) -> String {
+        let mut out = String::with_capacity(s.len() + 2);
+        out.push('"');
+        for b in s.bytes() {
+            match b {
+                b'"' => out.push_str("\\\""),
+                b'\\' => out.push_str("\\\\"),
+                b'\n' => out.push_str("\\n"),
+                b'\t' => out.push_str("\\t"),
+                b' '..=b'~' => out.push(b as char),
+                _ => write!(out, "\\{:03o}", b).unwrap()
+            }
+        }
+        out.push('"');
+        out
+    }
+
+    fn verilog_bit(bit: Bit) -> String {
+        match bit {
+            Bit::Const(c) => format!("1'b{}", c),
+            Bit::Net(id) => format!("_{}_", id)
+        }
+    }
+
+    fn verilog_concat(bits: &[Bit]) -> String {
+        match bits.len() {
+            0 => String::new(),
+            1 => Self::verilog_bit(bits[0]),
+            _ => format!("{{{}}}", bits.iter().rev().map(|b| Self::verilog_bit(*b)).collect::<Vec<_>>().join(", "))
+        }
+    }
+
+    fn verilog_operand(cell: &ExportCell, port: &str) -> String {
+        let signed = cell.param(&[port, "_SIGNED"].concat()).unwrap_or(0) != 0;
+        let bits = Self::verilog_concat(cell.port(port));
+        match signed {
+            true => format!("$signed({})", bits),
+            false => bits
+        }
+    }
+
+    fn verilog_expr(cell: &ExportCell) -> Option<String> {
+        let lookup = |ops: &[(&str, &'static str)]| ops.iter().find(|(ty, _)| *ty == cell.ty).map(|(_, op)| *op);
+        if let Some(op) = lookup(Self::VERILOG_AY_OPS) {
+            Some(format!("{}{}", op, Self::verilog_operand(cell, "A")))
+        }
+        else if matches!(cell.ty.as_str(), "$shift" | "$shiftx") && cell.param("B_SIGNED").unwrap_or(0) != 0 {
+            // a negative amount shifts left, while Verilog reads every shift amount as unsigned
+            let (a, b) = (Self::verilog_operand(cell, "A"), Self::verilog_concat(cell.port("B")));
+            Some(format!("$signed({1}) < 0 ? {0} << -$signed({1}) : {0} >> {1}", a, b))
+        }
+        else if let Some(op) = lookup(Self::VERILOG_ABY_OPS) {
+            Some(format!("{} {} {}", Self::verilog_operand(cell, "A"), op, Self::verilog_operand(cell, "B")))
+        }
+        else if let Some(op) = lookup(Self::VERILOG_ABY_NEG_OPS) {
+            Some(format!("~({} {} {})", Self::verilog_operand(cell, "A"), op, Self::verilog_operand(cell, "B")))
+        }
+        else if cell.ty == "$mux" {
+            Some(format!("{} ? {} : {}", Self::verilog_operand(cell, "S"), Self::verilog_operand(cell, "B"), Self::verilog_operand(cell, "A")))
+        }
+        else {
+            None
+        }
+    }
+
+    fn verilog_param(value: &serde_json::Value) -> String {
+        match value {
+            serde_json::Value::String(s) if !s.is_empty() && ParamBits::parse(s).is_some() => format!("{}'b{}", s.len(), s),
+            serde_json::Value::String(s) => Self::verilog_string(s.strip_suffix(' ').unwrap_or(s)),  // Yosys pads bit-like strings with a space
+            other => other.to_string()
+        }
+    }
+
+    /// The always block of a register, or None for set/reset flops, whose per-bit set and reset need an instance.
+    fn verilog_register(cell: &ExportCell, reg: &str) -> Option<String> {
+        if cell.port("SET").len() + cell.port("CLR").len() > 0 {
+            return None;
+        }
+        let active_high = |pol: &str| cell.param(pol).unwrap_or(1) != 0;
+        let edge = |port: &str| match active_high(&[port, "_POLARITY"].concat()) {
+            true => format!("posedge {}", Self::verilog_concat(cell.port(port))),
+            false => format!("negedge {}", Self::verilog_concat(cell.port(port)))
+        };
+        let cond = |port: &str| match active_high(&[port, "_POLARITY"].concat()) {
+            true => Self::verilog_concat(cell.port(port)),
+            false => format!("!{}", Self::verilog_concat(cell.port(port)))
+        };
+        let is_latch = cell.port("CLK").is_empty();
+        let op = if is_latch { "=" } else { "<=" };
+        let value = |param: &str| Self::verilog_param(&cell.params[param]);
+
+        let mut sens = Vec::new();
+        let mut body = format!("{} {} {};", reg, op, Self::verilog_concat(cell.port("D")));
+        if !is_latch {
+            sens.push(edge("CLK"));
+        }
+        if cell.ty == "$sdffce" {   // the synchronous reset only acts when enabled
+            body = format!("if ({}) {} {} {}; else {}", cond("SRST"), reg, op, value("SRST_VALUE"), body);
+            body = format!("if ({}) begin {} end", cond("EN"), body);
+        }
+        else {
+            if !cell.port("EN").is_empty() {
+                body = format!("if ({}) {}", cond("EN"), body);
+            }
+            if !cell.port("SRST").is_empty() {
+                body = format!("if ({}) {} {} {}; else {}", cond("SRST"), reg, op, value("SRST_VALUE"), body);
+            }
+        }
+        if !cell.port("ARST").is_empty() {
+            if !is_latch {
+                sens.push(edge("ARST"));
+            }
+            body = format!("if ({}) {} {} {}; else {}", cond("ARST"), reg, op, value("ARST_VALUE"), body);
+        }
+        if !cell.port("ALOAD").is_empty() {
+            sens.push(edge("ALOAD"));
+            body = format!("if ({}) {} {} {}; else {}", cond("ALOAD"), reg, op, Self::verilog_concat(cell.port("AD")), body);
+        }
+        match sens.is_empty() {
+            true => Some(format!("always @* {}", body)),
+            false => Some(format!("always @({}) {}", sens.join(" or "), body))
+        }
+    }
+
+    /// Opaque cells are emitted as instances of their original type.
+    fn verilog_instance(cell: &ExportCell, name: &str) -> String {
+        let params: Vec<_> = cell.params.iter()
+            .map(|(p, v)| format!(".{}({})", Self::verilog_ident(p), Self::verilog_param(v)))
+            .collect();
+        let conns: Vec<_> = cell.conns.iter()
+            .map(|(p, _, bits)| format!(".{}({})", Self::verilog_ident(p), Self::verilog_concat(bits)))
+            .collect();
+        match params.is_empty() {
+            true => format!("{} {} ({});", Self::verilog_ident(&cell.ty), name, conns.join(", ")),
+            false => format!("{} #({}) {} ({});", Self::verilog_ident(&cell.ty), params.join(", "), name, conns.join(", "))
+        }
+    }
+
+    /// Emits every row in the cell tables as a structural Verilog module.
+    /// Like `export_json`, of the rows driving the same net only the first keeps it.
+    pub fn export_verilog(&self, top_mod: &str) -> Result<String, VerilogError> {
+        self.export_rows_verilog(top_mod, &self.all_rows())
+    }
+
+    /// Emits the given rows (e.g. an extraction result) as a synthesizable Verilog module with one driver per net.
+    /// Fails with every internal cell type that has neither a Verilog operator nor a register form.
+    pub fn export_rows_verilog(&self, top_mod: &str, rows: &CellRows) -> Result<String, VerilogError> {
+        let mut bitmap = BitMap::new(self);
+        let ports = self.lower_ports(&mut bitmap);
+        let mut cells = self.lower_cells(rows, &mut bitmap);
+        Self::unique_drivers(&ports, &mut cells, &mut bitmap);
+        let netnames = self.lower_netnames(&ports, &mut bitmap);
+        Self::module_verilog(top_mod, &ports, &cells, &netnames)
+    }
+
+    fn module_verilog(top_mod: &str, ports: &[ExportPort], cells: &[ExportCell], netnames: &[ExportNetName]) -> Result<String, VerilogError> {
+        let mut nets = BTreeSet::new();
+        for bit in ports.iter().flat_map(|p| p.bits.iter()).chain(cells.iter().flat_map(|c| c.conns.iter().flat_map(|(_, _, b)| b.iter()))) {
+            if let Bit::Net(id) = bit {
+                nets.insert(*id);
+            }
+        }
+
+        let mut out = String::new();
+        let port_names: Vec<_> = ports.iter().map(|p| Self::verilog_ident(&p.name)).collect();
+        writeln!(out, "module {}({});", Self::verilog_ident(top_mod), port_names.join(", ")).unwrap();
+        for (port, name) in ports.iter().zip(port_names.iter()) {
+            match port.bits.len() {
+                1 => writeln!(out, "  {} {};", port.direction, name).unwrap(),
+                n => writeln!(out, "  {} [{}:0] {};", port.direction, n - 1, name).unwrap()
+            }
+        }
+        for net in nets.iter() {
+            writeln!(out, "  wire _{}_;", net).unwrap();
+        }
+
+        // visible netnames on connected nets become named wires
+        for netname in netnames.iter().filter(|n| !n.hide_name && !n.bits.is_empty()) {
+            if !netname.bits.iter().all(|b| matches!(b, Bit::Const(_)) || matches!(b, Bit::Net(id) if nets.contains(id))) {
+                continue;
+            }
+            let name = Self::verilog_ident(&netname.name);
+            if let Some(src) = &netname.src {
+                writeln!(out, "  (* src = {} *)", Self::verilog_string(src)).unwrap();
+            }
+            match netname.bits.len() {
+                1 => writeln!(out, "  wire {};", name).unwrap(),
+                n => writeln!(out, "  wire [{}:0] {};", n - 1, name).unwrap()
+            }
+            writeln!(out, "  assign {} = {};", name, Self::verilog_concat(&netname.bits)).unwrap();
+        }
+
+        // connect ports to nets
+        for (port, name) in ports.iter().zip(port_names.iter()) {
+            for (i, bit) in port.bits.iter().enumerate() {
+                let port_bit = match port.bits.len() {
+                    1 => name.clone(),
+                    _ => format!("{}[{}]", name, i)
+                };
+                match (port.direction, bit) {
+                    ("input", Bit::Net(_)) => writeln!(out, "  assign {} = {};", Self::verilog_bit(*bit), port_bit).unwrap(),
+                    ("input", Bit::Const(_)) => {},
+                    _ => writeln!(out, "  assign {} = {};", port_bit, Self::verilog_bit(*bit)).unwrap()
+                }
+            }
+        }
+
+        // one assign per combinational row, one always block per register, one instance per opaque cell
+        let mut unmappable = BTreeSet::new();
+        for (i, cell) in cells.iter().enumerate() {
+            let reg = format!("_reg{}_", i);
+            let always = match Self::RTLIL_REG_TYPES.iter().any(|r| r.0 == cell.ty) {
+                true => Self::verilog_register(cell, &reg),
+                false => None
+            };
+            if let Some(always) = always {
+                let width = cell.param("WIDTH").unwrap();
+                match width {
+                    1 => writeln!(out, "  reg {};", reg).unwrap(),
+                    _ => writeln!(out, "  reg [{}:0] {};", width - 1, reg).unwrap()
+                }
+                writeln!(out, "  {}", always).unwrap();
+                writeln!(out, "  assign {} = {};", Self::verilog_concat(cell.port("Q")), reg).unwrap();
+            }
+            else if let Some(expr) = Self::verilog_expr(cell) {
+                writeln!(out, "  assign {} = {};", Self::verilog_concat(cell.port("Y")), expr).unwrap();
+            }
+            else if cell.ty.starts_with('$') && !cell.ty.starts_with("$paramod") {
+                unmappable.insert(cell.ty.clone());     // an internal cell type, so no module to instantiate
+            }
+            else {
+                writeln!(out, "  {}", Self::verilog_instance(cell, &format!("_cell{}_", i))).unwrap();
+            }
+        }
+        writeln!(out, "endmodule").unwrap();
+        match unmappable.is_empty() {
+            true => Ok(out),
+            false => Err(VerilogError::UnmappableTypes { types: unmappable.into_iter().collect() })
+        }
+    }
+}
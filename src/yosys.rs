@@ -0,0 +1,141 @@
+use serde::de::{self, Deserializer, Visitor};
+use serde::Deserialize;
+use std::{collections::BTreeMap, fmt, fs::File, io::BufReader};
+use crate::error::NetlistError;
+
+
+/// Parameters and attributes, kept as JSON since their meaning depends on the cell type.
+pub type Params = serde_json::Map<String, serde_json::Value>;
+
+/// A Yosys JSON netlist, as written by `write_json`. Sections the importer does not use
+/// (memories, parameter defaults, ...) are skipped while parsing.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Netlist {
+    #[serde(default)]
+    pub creator: Option<String>,
+    #[serde(default)]
+    pub modules: BTreeMap<String, Module>
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Module {
+    #[serde(default)]
+    pub attributes: Params,
+    #[serde(default)]
+    pub ports: BTreeMap<String, Port>,
+    #[serde(default)]
+    pub cells: BTreeMap<String, Cell>,
+    #[serde(default)]
+    pub netnames: BTreeMap<String, Netname>
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Port {
+    pub direction: String,
+    pub bits: Vec<JsonBit>   // LSB first
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Cell {
+    #[serde(default)]
+    pub hide_name: Option<i64>,
+    #[serde(rename = "type")]
+    pub ty: String,
+    #[serde(default)]
+    pub parameters: Params,
+    #[serde(default)]
+    pub attributes: Params,
+    #[serde(default)]
+    pub port_directions: Option<BTreeMap<String, String>>,
+    #[serde(default)]
+    pub connections: BTreeMap<String, Vec<JsonBit>>
+}
+
+/// An entry of the `netnames` section, a named signal of the module.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Netname {
+    #[serde(default)]
+    pub hide_name: Option<i64>,
+    pub bits: Vec<JsonBit>,
+    #[serde(default)]
+    pub attributes: Params
+}
+
+/// A bit of a signal: a net id, or a constant written as a one-character string (`"0"`, `"1"`, `"x"` or `"z"`).
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum JsonBit {
+    Net(i64),
+    Const(char)
+}
+
+impl JsonBit {
+    pub fn net(self) -> Option<i64> {
+        match self {
+            JsonBit::Net(id) => Some(id),
+            JsonBit::Const(_) => None
+        }
+    }
+
+    pub fn to_json(self) -> serde_json::Value {
+        match self {
+            JsonBit::Net(id) => serde_json::json!(id),
+            JsonBit::Const(c) => serde_json::json!(c.to_string())
+        }
+    }
+}
+
+// a hand-written visitor, as an untagged enum would buffer every bit before trying its variants
+impl<'de> Deserialize<'de> for JsonBit {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct BitVisitor;
+
+        impl Visitor<'_> for BitVisitor {
+            type Value = JsonBit;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a net id or a constant bit")
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<JsonBit, E> {
+                Ok(JsonBit::Net(v))
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<JsonBit, E> {
+                i64::try_from(v).map(JsonBit::Net).map_err(|_| E::invalid_value(de::Unexpected::Unsigned(v), &self))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<JsonBit, E> {
+                let mut chars = v.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Ok(JsonBit::Const(c)),
+                    _ => Err(E::invalid_value(de::Unexpected::Str(v), &self))
+                }
+            }
+        }
+
+        deserializer.deserialize_any(BitVisitor)
+    }
+}
+
+impl Cell {
+    /// The `src` attribute, where the cell comes from in the HDL sources.
+    pub fn src(&self) -> Option<&str> {
+        self.attributes.get("src").and_then(|s| s.as_str())
+    }
+}
+
+impl Netname {
+    pub fn src(&self) -> Option<&str> {
+        self.attributes.get("src").and_then(|s| s.as_str())
+    }
+}
+
+impl Netlist {
+    /// Parses a whole netlist file into memory. `NetlistDatabase::build_from_json` streams the file instead.
+    pub fn load(json_path: &str) -> Result<Self, NetlistError> {
+        let file = File::open(json_path)
+            .map_err(|source| NetlistError::Io { path: json_path.to_string(), source })?;
+        serde_json::from_reader(BufReader::new(file))
+            .map_err(|source| NetlistError::Json { path: json_path.to_string(), source })
+    }
+}
@@ -0,0 +1,107 @@
+mod common;
+
+use common::{cells, import_json};
+use core_relations::{PlanStrategy, Value};
+use eggrtl::core::NetlistDatabase;
+use eggrtl::saturate::{RuleSet, RunConfig};
+use numeric_id::NumericId;
+use serde_json::json;
+
+
+fn add(a: &[i64], b: &[i64], y: &[i64]) -> serde_json::Value {
+    json!({
+        "type": "$add",
+        "parameters": { "A_SIGNED": 0, "B_SIGNED": 0, "A_WIDTH": a.len(), "B_WIDTH": b.len(), "Y_WIDTH": y.len() },
+        "connections": { "A": a, "B": b, "Y": y }
+    })
+}
+
+/// `y = (a + b) + c` on four bits.
+fn add3() -> NetlistDatabase {
+    import_json(json!({
+        "modules": {
+            "top": {
+                "ports": {
+                    "a": { "direction": "input", "bits": [2, 3, 4, 5] },
+                    "b": { "direction": "input", "bits": [6, 7, 8, 9] },
+                    "c": { "direction": "input", "bits": [10, 11, 12, 13] },
+                    "y": { "direction": "output", "bits": [14, 15, 16, 17] }
+                },
+                "cells": {
+                    "ab": add(&[2, 3, 4, 5], &[6, 7, 8, 9], &[18, 19, 20, 21]),
+                    "abc": add(&[18, 19, 20, 21], &[10, 11, 12, 13], &[14, 15, 16, 17])
+                }
+            }
+        }
+    }), "top")
+}
+
+fn rewrite_basic_once(db: &mut NetlistDatabase) -> serde_json::Value {
+    db.rewrite_basic_all(PlanStrategy::PureSize, Value::new(0)..Value::new(1));
+    db.merge_all();
+    db.export_json("top")
+}
+
+#[test]
+fn assoc_inserts_the_right_leaning_adders() {
+    let mut db = add3();
+    let json = rewrite_basic_once(&mut db);
+    let adders: Vec<&serde_json::Value> = json["modules"]["top"]["cells"].as_object().unwrap()
+        .values()
+        .filter(|c| c["type"] == "$add")
+        .map(|c| &c["connections"])
+        .collect();
+
+    let bc = adders.iter()
+        .find(|c| c["A"] == json!([6, 7, 8, 9]) && c["B"] == json!([10, 11, 12, 13]))
+        .expect("No b + c adder");
+    assert!(adders.iter().any(|c| c["A"] == json!([2, 3, 4, 5]) && c["B"] == bc["Y"]), "No a + (b + c) adder");
+}
+
+#[test]
+fn assoc_reuses_its_wires_when_the_same_match_comes_again() {
+    let mut db = add3();
+    let first = rewrite_basic_once(&mut db);
+    let second = rewrite_basic_once(&mut db);
+    assert_eq!(cells(&second, "top"), cells(&first, "top"));
+}
+
+#[test]
+fn later_iterations_match_new_rows_against_old_ones() {
+    // ((a + b) + c) + d, where a + (b + (c + d)) needs (a + b) + (c + d) from the first iteration
+    let mut db = import_json(json!({
+        "modules": {
+            "top": {
+                "ports": {
+                    "a": { "direction": "input", "bits": [2, 3] },
+                    "b": { "direction": "input", "bits": [4, 5] },
+                    "c": { "direction": "input", "bits": [6, 7] },
+                    "d": { "direction": "input", "bits": [8, 9] },
+                    "y": { "direction": "output", "bits": [10, 11] }
+                },
+                "cells": {
+                    "ab": add(&[2, 3], &[4, 5], &[12, 13]),
+                    "abc": add(&[12, 13], &[6, 7], &[14, 15]),
+                    "abcd": add(&[14, 15], &[8, 9], &[10, 11])
+                }
+            }
+        }
+    }), "top");
+    db.saturate(&RunConfig { rule_sets: vec![RuleSet::Basic], iter_limit: 2, ..RunConfig::default() });
+    let json = db.export_json("top");
+    let adders: Vec<&serde_json::Value> = json["modules"]["top"]["cells"].as_object().unwrap()
+        .values()
+        .filter(|c| c["type"] == "$add")
+        .map(|c| &c["connections"])
+        .collect();
+    // outputs of the adders of a and b in either order, one of which drives the net while the others dangle
+    let sums = |a: &serde_json::Value, b: &serde_json::Value| -> Vec<serde_json::Value> {
+        adders.iter()
+            .filter(|c| (&c["A"] == a && &c["B"] == b) || (&c["A"] == b && &c["B"] == a))
+            .map(|c| c["Y"].clone())
+            .collect()
+    };
+
+    let bcd: Vec<_> = sums(&json!([6, 7]), &json!([8, 9])).iter().flat_map(|cd| sums(&json!([4, 5]), cd)).collect();
+    assert!(bcd.iter().any(|bcd| !sums(&json!([2, 3]), bcd).is_empty()), "No a + (b + (c + d)) adders");
+}
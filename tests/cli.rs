@@ -0,0 +1,34 @@
+mod common;
+
+use common::fixture;
+use std::process::{Command, Output};
+
+
+fn eggrtl(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_eggrtl")).args(args).output().expect("Failed to run eggrtl")
+}
+
+#[test]
+fn stats_prints_the_table_sizes() {
+    let out = eggrtl(&["stats", &fixture("mac.json")]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("cells:      7"), "{}", stdout);
+}
+
+#[test]
+fn each_kind_of_failure_has_its_own_exit_code() {
+    let mac = fixture("mac.json");
+    let too_long = "9".repeat(400);     // overflows a Duration
+    let cases: [(&[&str], i32); 5] = [
+        (&["optimize", &mac, "--rules", "nope"], 2),
+        (&["optimize", &mac, "--timeout", &too_long], 2),
+        (&["stats", &fixture("missing.json")], 3),
+        (&["optimize", &mac, "--rules", "missing.rules"], 4),
+        (&["export", &mac, "-o", "/nonexistent/out.json"], 5)
+    ];
+    for (args, code) in cases {
+        let out = eggrtl(args);
+        assert_eq!(out.status.code(), Some(code), "{:?}: {}", args, String::from_utf8_lossy(&out.stderr));
+    }
+}
@@ -0,0 +1,102 @@
+#![allow(dead_code)]    // each test file uses some of the helpers
+
+use eggrtl::core::NetlistDatabase;
+use eggrtl::error::NetlistError;
+use eggrtl::hierarchy::Hierarchy;
+use eggrtl::yosys::Netlist;
+use serde_json::Value;
+
+
+/// Path of a netlist in `tests/fixtures`.
+pub fn fixture(name: &str) -> String {
+    format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+/// Imports `top` of a netlist file parsed as a whole.
+pub fn import_file(path: &str, top: &str, hierarchy: Hierarchy) -> NetlistDatabase {
+    let netlist = Netlist::load(path).unwrap_or_else(|err| panic!("{}", err));
+    import_netlist(&netlist, top, &[], hierarchy).unwrap_or_else(|err| panic!("{}", err))
+}
+
+/// Imports `top` of a netlist given as JSON, e.g. one written by `export_json`.
+pub fn import_json(json: Value, top: &str) -> NetlistDatabase {
+    try_import_json(json, top, &[], Hierarchy::Flatten).unwrap_or_else(|err| panic!("{}", err))
+}
+
+/// Imports `top` of a netlist given as JSON, clocked by the named ports (or by any, with no names).
+pub fn try_import_json(json: Value, top: &str, clks: &[&str], hierarchy: Hierarchy) -> Result<NetlistDatabase, NetlistError> {
+    let netlist: Netlist = serde_json::from_value(json).expect("Not a Yosys netlist");
+    import_netlist(&netlist, top, clks, hierarchy)
+}
+
+fn import_netlist(netlist: &Netlist, top: &str, clks: &[&str], hierarchy: Hierarchy) -> Result<NetlistDatabase, NetlistError> {
+    let mut db = NetlistDatabase::default();
+    db.build_mod(top, &netlist.modules, clks, hierarchy)?;
+    Ok(db)
+}
+
+/// The cells of module `top` of an exported netlist, without their names (which number them in
+/// table order) and sorted, so netlists with the same cells compare equal.
+pub fn cells(json: &Value, top: &str) -> Vec<String> {
+    let cells = json["modules"][top]["cells"].as_object().expect("No cells in the exported module");
+    let mut cells: Vec<String> = cells.values().map(|c| c.to_string()).collect();
+    cells.sort();
+    cells
+}
+
+/// Asserts that two databases hold the same netlist: the same table sizes, and exports with the
+/// same ports, netnames and cells on the same nets.
+pub fn assert_same_tables(a: &NetlistDatabase, b: &NetlistDatabase, top: &str) {
+    assert_eq!(a.stats(), b.stats());
+    let (a, b) = (a.export_json(top), b.export_json(top));
+    assert_eq!(a["modules"][top]["ports"], b["modules"][top]["ports"]);
+    assert_eq!(a["modules"][top]["netnames"], b["modules"][top]["netnames"]);
+    assert_eq!(cells(&a, top), cells(&b, top));
+}
+
+/// Asserts that every net of module `top` has at most one driver, an input port or a cell output,
+/// and that no cell output is a constant.
+pub fn assert_one_driver_per_net(json: &Value, top: &str) {
+    let module = &json["modules"][top];
+    let mut driven: Vec<&Value> = module["ports"].as_object().expect("No ports in the exported module")
+        .values()
+        .filter(|p| p["direction"] == "input")
+        .flat_map(|p| p["bits"].as_array().unwrap())
+        .collect();
+    for cell in module["cells"].as_object().expect("No cells in the exported module").values() {
+        for (port, bits) in cell["connections"].as_object().unwrap() {
+            if cell["port_directions"][port] == "output" {
+                driven.extend(bits.as_array().unwrap());
+            }
+        }
+    }
+    let mut nets: Vec<i64> = driven.iter()
+        .map(|b| b.as_i64().unwrap_or_else(|| panic!("A cell drives the constant {}", b)))
+        .collect();
+    nets.sort();
+    let drivers = nets.len();
+    nets.dedup();
+    assert_eq!(nets.len(), drivers, "Some nets have several drivers");
+}
+
+/// Asserts that every net of an output port of module `top` is driven, by an input port or a cell output.
+pub fn assert_outputs_driven(json: &Value, top: &str) {
+    let module = &json["modules"][top];
+    let ports = module["ports"].as_object().expect("No ports in the exported module");
+    let mut driven: Vec<&Value> = ports.values()
+        .filter(|p| p["direction"] == "input")
+        .flat_map(|p| p["bits"].as_array().unwrap())
+        .collect();
+    for cell in module["cells"].as_object().expect("No cells in the exported module").values() {
+        for (port, bits) in cell["connections"].as_object().unwrap() {
+            if cell["port_directions"][port] == "output" {
+                driven.extend(bits.as_array().unwrap());
+            }
+        }
+    }
+    for (name, port) in ports.iter().filter(|(_, p)| p["direction"] == "output") {
+        for bit in port["bits"].as_array().unwrap().iter().filter(|b| b.is_number()) {
+            assert!(driven.contains(&bit), "Bit {} of output `{}` is not driven", bit, name);
+        }
+    }
+}
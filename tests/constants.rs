@@ -0,0 +1,77 @@
+mod common;
+
+use common::import_json;
+use eggrtl::saturate::{RuleSet, RunConfig};
+use serde_json::json;
+
+
+/// `y = a << 0` next to an output port tied to constant bits.
+fn shift_by_zero() -> serde_json::Value {
+    json!({
+        "modules": {
+            "top": {
+                "ports": {
+                    "a": { "direction": "input", "bits": [2, 3, 4, 5] },
+                    "tied": { "direction": "output", "bits": ["0", "1"] },
+                    "y": { "direction": "output", "bits": [6, 7, 8, 9] }
+                },
+                "cells": {
+                    "shl": {
+                        "type": "$shl",
+                        "parameters": { "A_SIGNED": "0", "B_SIGNED": "0", "A_WIDTH": 4, "B_WIDTH": 2, "Y_WIDTH": 4 },
+                        "connections": { "A": [2, 3, 4, 5], "B": ["0", "0"], "Y": [6, 7, 8, 9] }
+                    }
+                }
+            }
+        }
+    })
+}
+
+#[test]
+fn constant_tied_port_keeps_constant_bits() {
+    let db = import_json(shift_by_zero(), "top");
+    let json = db.export_json("top");
+    assert_eq!(json["modules"]["top"]["ports"]["tied"]["bits"], json!(["0", "1"]));
+    let cells = common::cells(&json, "top");
+    assert!(cells[0].contains(r#""B":["0","0"]"#), "shift amount is not constant: {}", cells[0]);
+}
+
+#[test]
+fn constant_tied_port_keeps_shift_by_zero_matching() {
+    let mut db = import_json(shift_by_zero(), "top");
+    let report = db.saturate(&RunConfig { rule_sets: vec![RuleSet::Shift], ..RunConfig::default() });
+    assert!(report.unions > 0);
+
+    let ports = &db.export_json("top")["modules"]["top"]["ports"];
+    assert_eq!(ports["y"]["bits"], ports["a"]["bits"]);
+}
+
+/// `y = 3 + 4` on three bits.
+fn constant_add() -> serde_json::Value {
+    json!({
+        "modules": {
+            "top": {
+                "ports": {
+                    "y": { "direction": "output", "bits": [2, 3, 4] }
+                },
+                "cells": {
+                    "add": {
+                        "type": "$add",
+                        "parameters": { "A_SIGNED": 0, "B_SIGNED": 0, "A_WIDTH": 3, "B_WIDTH": 3, "Y_WIDTH": 3 },
+                        "connections": { "A": ["1", "1", "0"], "B": ["0", "0", "1"], "Y": [2, 3, 4] }
+                    }
+                }
+            }
+        }
+    })
+}
+
+#[test]
+fn folded_constants_are_exported() {
+    let mut db = import_json(constant_add(), "top");
+    let report = db.saturate(&RunConfig { rule_sets: vec![RuleSet::Fold], ..RunConfig::default() });
+    assert!(report.unions > 0);
+
+    let json = db.export_json("top");
+    assert_eq!(json["modules"]["top"]["ports"]["y"]["bits"], json!(["1", "1", "1"]));
+}
@@ -0,0 +1,36 @@
+mod common;
+
+use common::{fixture, import_file, import_json};
+use eggrtl::hierarchy::Hierarchy;
+use serde_json::json;
+
+
+#[test]
+fn export_egglog_writes_the_prelude_and_a_union_per_row() {
+    let db = import_file(&fixture("mac.json"), "top", Hierarchy::Flatten);
+    let program = db.export_egglog();
+    assert!(program.starts_with("(datatype Wire "), "{}", program);
+
+    // the ROM is opaque and left out
+    let count = |ctor: &str| program.lines().filter(|l| l.starts_with(&format!("(union ({} ", ctor))).count();
+    assert_eq!((count("Ay"), count("Aby"), count("Absy"), count("Reg")), (1, 3, 1, 1), "{}", program);
+    assert!(program.lines().any(|l| l.starts_with(r#"(union (Aby "$mulu" (Bits (vec-of (Net "#)), "{}", program);
+    assert!(program.lines().any(|l| l.starts_with(r#"(union (Reg "$dff CLK_POLARITY=1" "#)), "{}", program);
+}
+
+#[test]
+fn export_egglog_escapes_port_names() {
+    let db = import_json(json!({
+        "modules": {
+            "top": {
+                "ports": {
+                    "a\"b\\c": { "direction": "input", "bits": [2] },
+                    "y": { "direction": "output", "bits": [2] }
+                },
+                "cells": {}
+            }
+        }
+    }), "top");
+    let program = db.export_egglog();
+    assert!(program.lines().any(|l| l.starts_with(r#"(union (InputBit "a\"b\\c" 0) (Net "#)), "{}", program);
+}
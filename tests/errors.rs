@@ -0,0 +1,116 @@
+mod common;
+
+use common::{fixture, try_import_json};
+use eggrtl::core::NetlistDatabase;
+use eggrtl::error::{Location, NetlistError};
+use eggrtl::hierarchy::Hierarchy;
+use serde_json::json;
+
+
+/// Module `top` with a clock, a 2-bit input and a 2-bit output around the given cells.
+fn with_cells(cells: serde_json::Value) -> serde_json::Value {
+    json!({
+        "modules": {
+            "top": {
+                "ports": {
+                    "clk": { "direction": "input", "bits": [2] },
+                    "a": { "direction": "input", "bits": [3, 4] },
+                    "y": { "direction": "output", "bits": [5, 6] }
+                },
+                "cells": cells
+            }
+        }
+    })
+}
+
+fn import_error(cells: serde_json::Value) -> NetlistError {
+    match try_import_json(with_cells(cells), "top", &["clk"], Hierarchy::Flatten) {
+        Ok(_) => panic!("The netlist imported without an error"),
+        Err(err) => err
+    }
+}
+
+#[test]
+fn unreadable_files_and_missing_modules_are_reported() {
+    let mut db = NetlistDatabase::default();
+    let err = db.build_from_json(&fixture("missing.json"), "top", &["clk"], Hierarchy::Flatten).unwrap_err();
+    assert!(matches!(&err, NetlistError::Io { path, .. } if path.ends_with("missing.json")), "{:?}", err);
+
+    let err = db.build_from_json(&fixture("mac.json"), "nope", &["clk"], Hierarchy::Flatten).unwrap_err();
+    assert!(matches!(&err, NetlistError::MissingModule { module } if module == "nope"), "{:?}", err);
+}
+
+#[test]
+fn bad_bits_are_located_at_their_cell() {
+    let err = import_error(json!({
+        "not": {
+            "type": "$not",
+            "parameters": { "A_SIGNED": 0, "A_WIDTH": 2, "Y_WIDTH": 2 },
+            "attributes": { "src": "top.v:3.5-3.9" },
+            "connections": { "A": [3, "q"], "Y": [5, 6] }
+        }
+    }));
+    match &err {
+        NetlistError::BadBit { loc, bit } => {
+            assert_eq!(loc, &Location::cell("top", "not", Some("top.v:3.5-3.9")));
+            assert_eq!(bit, &json!("q"));
+        },
+        _ => panic!("{:?}", err)
+    }
+    assert_eq!(err.to_string(), r#"module `top`, cell `not` (top.v:3.5-3.9): invalid bit "q""#);
+}
+
+#[test]
+fn missing_connections_name_the_port() {
+    let err = import_error(json!({
+        "not": {
+            "type": "$not",
+            "parameters": { "A_SIGNED": 0, "A_WIDTH": 2, "Y_WIDTH": 2 },
+            "connections": { "A": [3, 4] }
+        }
+    }));
+    assert!(matches!(&err, NetlistError::MissingPort { loc, port } if port == "Y" && loc == &Location::cell("top", "not", None)), "{:?}", err);
+}
+
+#[test]
+fn registers_on_another_clock_are_rejected() {
+    let err = import_error(json!({
+        "ff": {
+            "type": "$dff",
+            "parameters": { "CLK_POLARITY": 1, "WIDTH": 2 },
+            "connections": { "CLK": [3], "D": [3, 4], "Q": [5, 6] }
+        }
+    }));
+    assert!(matches!(&err, NetlistError::ClockMismatch { loc, found, .. } if found == &[3] && loc.cell.as_deref() == Some("ff")), "{:?}", err);
+}
+
+#[test]
+fn clocks_must_name_input_ports() {
+    for clk in ["clock", "y"] {
+        match try_import_json(with_cells(json!({})), "top", &[clk], Hierarchy::Flatten) {
+            Err(NetlistError::MissingClock { port, .. }) => assert_eq!(port, clk),
+            other => panic!("{:?}", other.map(|_| ()))
+        }
+    }
+}
+
+#[test]
+fn widths_must_match_the_connected_bits() {
+    let err = import_error(json!({
+        "mux": {
+            "type": "$mux",
+            "parameters": { "WIDTH": 2 },
+            "connections": { "A": [3, 4], "B": [3], "S": [4], "Y": [5, 6] }
+        }
+    }));
+    assert!(matches!(&err, NetlistError::BadParam { loc, param, .. } if param == "WIDTH" && loc.cell.as_deref() == Some("mux")), "{:?}", err);
+
+    let err = import_error(json!({
+        "not": {
+            "type": "$not",
+            "parameters": { "A_SIGNED": 0, "A_WIDTH": 2, "Y_WIDTH": 3 },
+            "connections": { "A": [3, 4], "Y": [5, 6] }
+        }
+    }));
+    assert!(matches!(&err, NetlistError::BadParam { param, value, .. } if param == "Y_WIDTH" && value == &json!(3)), "{:?}", err);
+}
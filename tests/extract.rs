@@ -0,0 +1,125 @@
+mod common;
+
+use common::{assert_one_driver_per_net, assert_outputs_driven, import_json};
+use eggrtl::extract::{AreaCost, ExtractMode};
+use serde_json::json;
+use std::time::Duration;
+
+
+fn aby_cell(ty: &str, a: &[i64], b: &[i64], y: &[i64]) -> serde_json::Value {
+    json!({
+        "type": ty,
+        "parameters": { "A_SIGNED": 0, "B_SIGNED": 0, "A_WIDTH": a.len(), "B_WIDTH": b.len(), "Y_WIDTH": y.len() },
+        "connections": { "A": a, "B": b, "Y": y }
+    })
+}
+
+/// `y = a * b` and `y = a + b` on four bits, as two drivers of the same net.
+fn mul_or_add() -> serde_json::Value {
+    json!({
+        "modules": {
+            "top": {
+                "ports": {
+                    "a": { "direction": "input", "bits": [2, 3, 4, 5] },
+                    "b": { "direction": "input", "bits": [6, 7, 8, 9] },
+                    "y": { "direction": "output", "bits": [10, 11, 12, 13] }
+                },
+                "cells": {
+                    "mul": aby_cell("$mul", &[2, 3, 4, 5], &[6, 7, 8, 9], &[10, 11, 12, 13]),
+                    "add": aby_cell("$add", &[2, 3, 4, 5], &[6, 7, 8, 9], &[10, 11, 12, 13])
+                }
+            }
+        }
+    })
+}
+
+#[test]
+fn greedy_extraction_picks_the_cheaper_equivalent_row() {
+    let db = import_json(mul_or_add(), "top");
+    let extraction = db.extract_greedy(&AreaCost);
+    assert_eq!(extraction.cost, 8.0);   // a 4-bit adder, where the multiplier costs 16
+
+    let json = db.export_rows_json("top", &extraction.rows);
+    let cells = common::cells(&json, "top");
+    assert_eq!(cells.len(), 1, "{:?}", cells);
+    assert!(cells[0].contains(r#""type":"$add""#), "{}", cells[0]);
+    assert_one_driver_per_net(&json, "top");
+    assert_outputs_driven(&json, "top");
+}
+
+/// `o1 = a * b` and `o2 = o1 + c`, or equivalently `o2 = c * d`, on four bits.
+/// Reusing the multiplier for `o2` costs an adder, while greedy counts the multiplier again.
+fn shared_mul() -> serde_json::Value {
+    json!({
+        "modules": {
+            "top": {
+                "ports": {
+                    "a": { "direction": "input", "bits": [2, 3, 4, 5] },
+                    "b": { "direction": "input", "bits": [6, 7, 8, 9] },
+                    "c": { "direction": "input", "bits": [10, 11, 12, 13] },
+                    "d": { "direction": "input", "bits": [14, 15, 16, 17] },
+                    "o1": { "direction": "output", "bits": [18, 19, 20, 21] },
+                    "o2": { "direction": "output", "bits": [22, 23, 24, 25] }
+                },
+                "cells": {
+                    "mul_ab": aby_cell("$mul", &[2, 3, 4, 5], &[6, 7, 8, 9], &[18, 19, 20, 21]),
+                    "add": aby_cell("$add", &[18, 19, 20, 21], &[10, 11, 12, 13], &[22, 23, 24, 25]),
+                    "mul_cd": aby_cell("$mul", &[10, 11, 12, 13], &[14, 15, 16, 17], &[22, 23, 24, 25])
+                }
+            }
+        }
+    })
+}
+
+#[test]
+fn dag_extraction_shares_subterms_that_greedy_counts_twice() {
+    let db = import_json(shared_mul(), "top");
+    let greedy = db.extract(&AreaCost, ExtractMode::Greedy);
+    assert_eq!(greedy.extraction.cost, 32.0);   // both multipliers
+
+    let report = db.extract(&AreaCost, ExtractMode::Dag { time_limit: Duration::from_secs(10) });
+    assert_eq!(report.extraction.cost, 24.0);   // one multiplier and the adder
+    assert!(report.optimal);
+    assert_eq!(report.gap(), 0.0);
+
+    let json = db.export_rows_json("top", &report.extraction.rows);
+    assert_eq!(common::cells(&json, "top").len(), 2);
+    assert_one_driver_per_net(&json, "top");
+    assert_outputs_driven(&json, "top");
+}
+
+#[test]
+fn dag_extraction_out_of_time_keeps_the_greedy_solution() {
+    let db = import_json(shared_mul(), "top");
+    let report = db.extract(&AreaCost, ExtractMode::Dag { time_limit: Duration::ZERO });
+    assert!(!report.optimal);
+    assert!(report.gap() >= 0.0);
+    assert!(report.lower_bound <= report.extraction.cost);
+    assert_eq!(report.extraction.cost, 32.0);
+}
+
+#[test]
+fn greedy_extraction_needs_no_driver_for_any_constant_bit() {
+    // high-impedance bits are constants too, like "x", "0" and "1"
+    let db = import_json(json!({
+        "modules": {
+            "top": {
+                "ports": {
+                    "a": { "direction": "input", "bits": [2, 3] },
+                    "y": { "direction": "output", "bits": [4, 5] }
+                },
+                "cells": {
+                    "and": {
+                        "type": "$and",
+                        "parameters": { "A_SIGNED": 0, "B_SIGNED": 0, "A_WIDTH": 2, "B_WIDTH": 2, "Y_WIDTH": 2 },
+                        "connections": { "A": [2, 3], "B": ["z", "x"], "Y": [4, 5] }
+                    }
+                }
+            }
+        }
+    }), "top");
+    let extraction = db.extract_greedy(&AreaCost);
+    let json = db.export_rows_json("top", &extraction.rows);
+    assert_eq!(common::cells(&json, "top").len(), 1);
+    assert_outputs_driven(&json, "top");
+}
@@ -0,0 +1,105 @@
+mod common;
+
+use common::import_json;
+use serde_json::json;
+
+
+/// An instance of `wrap`, whose outputs are its input `a`, the constant 1 and `~a[0]`.
+fn pass_through() -> serde_json::Value {
+    json!({
+        "modules": {
+            "wrap": {
+                "ports": {
+                    "a": { "direction": "input", "bits": [2, 3] },
+                    "y": { "direction": "output", "bits": [2, 3] },
+                    "k": { "direction": "output", "bits": ["1"] },
+                    "n": { "direction": "output", "bits": [4] }
+                },
+                "cells": {
+                    "not": {
+                        "type": "$not",
+                        "parameters": { "A_SIGNED": 0, "A_WIDTH": 1, "Y_WIDTH": 1 },
+                        "connections": { "A": [2], "Y": [4] }
+                    }
+                }
+            },
+            "top": {
+                "ports": {
+                    "x": { "direction": "input", "bits": [2, 3] },
+                    "y": { "direction": "output", "bits": [4, 5] },
+                    "k": { "direction": "output", "bits": [6] },
+                    "n": { "direction": "output", "bits": [7] }
+                },
+                "cells": {
+                    "u": {
+                        "type": "wrap",
+                        "connections": { "a": [2, 3], "y": [4, 5], "k": [6], "n": [7] }
+                    }
+                }
+            }
+        }
+    })
+}
+
+#[test]
+fn flattened_pass_through_and_constant_outputs_are_buffered() {
+    let db = import_json(pass_through(), "top");
+    let json = db.export_json("top");
+    let ports = &json["modules"]["top"]["ports"];
+    assert_eq!(ports["y"]["bits"], json!([4, 5]));
+    assert_eq!(ports["k"]["bits"], json!([6]));
+
+    let buffers: Vec<String> = common::cells(&json, "top").into_iter().filter(|c| c.contains(r#""type":"$pos""#)).collect();
+    assert_eq!(buffers.len(), 1, "{:?}", buffers);
+    assert!(buffers[0].contains(r#""A":["1",2,3]"#), "{}", buffers[0]);
+    assert!(buffers[0].contains(r#""Y":[6,4,5]"#), "{}", buffers[0]);
+}
+
+#[test]
+fn flattened_outputs_driven_by_cells_are_not_buffered() {
+    let db = import_json(pass_through(), "top");
+    let json = db.export_json("top");
+    let cells = common::cells(&json, "top");
+    assert_eq!(cells.len(), 2, "{:?}", cells);
+
+    let not = cells.iter().find(|c| c.contains(r#""type":"$not""#)).expect("No $not cell");
+    assert!(not.contains(r#""A":[2]"#), "{}", not);
+    assert!(not.contains(r#""Y":[7]"#), "{}", not);
+}
+
+#[test]
+fn blackbox_instances_stay_opaque_cells_with_their_port_directions() {
+    let json = json!({
+        "modules": {
+            "bb": {
+                "attributes": { "blackbox": "00000000000000000000000000000001" },
+                "ports": {
+                    "a": { "direction": "input", "bits": [2, 3] },
+                    "y": { "direction": "output", "bits": [4] }
+                },
+                "cells": {}
+            },
+            "top": {
+                "ports": {
+                    "x": { "direction": "input", "bits": [2, 3] },
+                    "y": { "direction": "output", "bits": [4] }
+                },
+                "cells": {
+                    "u": {
+                        "type": "bb",
+                        "parameters": {},
+                        "connections": { "a": [2, 3], "y": [4] }
+                    }
+                }
+            }
+        }
+    });
+    let db = import_json(json, "top");
+    let json = db.export_json("top");
+    common::assert_outputs_driven(&json, "top");
+
+    let cells = common::cells(&json, "top");
+    assert_eq!(cells.len(), 1, "{:?}", cells);
+    assert!(cells[0].contains(r#""type":"bb""#), "{}", cells[0]);
+    assert!(cells[0].contains(r#""port_directions":{"a":"input","y":"output"}"#), "{}", cells[0]);
+}
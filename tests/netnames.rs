@@ -0,0 +1,64 @@
+mod common;
+
+use common::import_json;
+use eggrtl::saturate::{RuleSet, RunConfig};
+use serde_json::json;
+
+
+/// `y = a << 0` through the named net `shifted`.
+fn named_shift() -> serde_json::Value {
+    json!({
+        "modules": {
+            "top": {
+                "ports": {
+                    "a": { "direction": "input", "bits": [2, 3, 4, 5] },
+                    "y": { "direction": "output", "bits": [6, 7, 8, 9] }
+                },
+                "cells": {
+                    "shl": {
+                        "type": "$shl",
+                        "parameters": { "A_SIGNED": 0, "B_SIGNED": 0, "A_WIDTH": 4, "B_WIDTH": 2, "Y_WIDTH": 4 },
+                        "connections": { "A": [2, 3, 4, 5], "B": ["0", "0"], "Y": [10, 11, 12, 13] }
+                    },
+                    "not": {
+                        "type": "$not",
+                        "parameters": { "A_SIGNED": 0, "A_WIDTH": 4, "Y_WIDTH": 4 },
+                        "connections": { "A": [10, 11, 12, 13], "Y": [6, 7, 8, 9] }
+                    }
+                },
+                "netnames": {
+                    "shifted": { "hide_name": 0, "bits": [10, 11, 12, 13], "attributes": { "src": "top.v:3 \"shl\"" } },
+                    "$auto$1": { "hide_name": 1, "bits": [6, 7, 8, 9], "attributes": {} }
+                }
+            }
+        }
+    })
+}
+
+#[test]
+fn netnames_follow_their_nets_through_unions() {
+    let mut db = import_json(named_shift(), "top");
+    let report = db.saturate(&RunConfig { rule_sets: vec![RuleSet::Shift], ..RunConfig::default() });
+    assert!(report.unions > 0);
+
+    let json = db.export_json("top");
+    let module = &json["modules"]["top"];
+    assert_eq!(module["netnames"]["shifted"]["bits"], module["ports"]["a"]["bits"]);
+    assert_eq!(module["netnames"]["shifted"]["attributes"]["src"], json!("top.v:3 \"shl\""));
+    assert_eq!(module["netnames"]["$auto$1"]["hide_name"], json!(1));
+}
+
+#[test]
+fn export_verilog_names_visible_nets_with_escaped_sources() {
+    let db = import_json(named_shift(), "top");
+    let verilog = db.export_verilog("top").unwrap_or_else(|err| panic!("{}", err));
+    let lines: Vec<&str> = verilog.lines().map(str::trim).collect();
+    for line in [
+        r#"(* src = "top.v:3 \"shl\"" *)"#,
+        "wire [3:0] shifted;",
+        "assign shifted = {_13_, _12_, _11_, _10_};"
+    ] {
+        assert!(lines.contains(&line), "missing `{}` in\n{}", line, verilog);
+    }
+    assert!(!verilog.contains("$auto$1"), "{}", verilog);
+}
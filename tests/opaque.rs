@@ -0,0 +1,46 @@
+mod common;
+
+use common::{cells, fixture, import_file, import_json};
+use eggrtl::hierarchy::Hierarchy;
+use serde_json::json;
+
+
+fn exported_cell(json: &serde_json::Value, ty: &str) -> serde_json::Value {
+    cells(json, "top").into_iter()
+        .map(|c| serde_json::from_str::<serde_json::Value>(&c).unwrap())
+        .find(|c| c["type"] == ty)
+        .unwrap_or_else(|| panic!("No {} cell is exported", ty))
+}
+
+#[test]
+fn unsupported_cells_keep_their_parameters_and_connections() {
+    let db = import_file(&fixture("mac.json"), "top", Hierarchy::Flatten);
+    let rom = exported_cell(&db.export_json("top"), "ROM16X4");
+    assert_eq!(rom["parameters"]["INIT"], json!("0001001000110100010101100111100010011010101111001101111011110000"));
+    assert_eq!(rom["port_directions"], json!({ "ADDR": "input", "DO": "output" }));
+    assert_eq!(rom["connections"], json!({ "ADDR": [3, 4, 5, 6], "DO": [33, 34, 35, 36] }));
+}
+
+#[test]
+fn cells_without_port_directions_drive_their_y_and_q_ports() {
+    let db = import_json(json!({
+        "modules": {
+            "top": {
+                "ports": {
+                    "a": { "direction": "input", "bits": [2, 3] },
+                    "y": { "direction": "output", "bits": [4] }
+                },
+                "cells": {
+                    "ne": {
+                        "type": "$ne",
+                        "parameters": { "A_SIGNED": 0, "B_SIGNED": 0, "A_WIDTH": 1, "B_WIDTH": 1, "Y_WIDTH": 1 },
+                        "connections": { "A": [2], "B": [3], "Y": [4] }
+                    }
+                }
+            }
+        }
+    }), "top");
+    let ne = exported_cell(&db.export_json("top"), "$ne");
+    assert_eq!(ne["port_directions"], json!({ "A": "input", "B": "input", "Y": "output" }));
+    assert_eq!(ne["connections"]["Y"], json!([4]));
+}
@@ -0,0 +1,35 @@
+mod common;
+
+use common::fixture;
+use eggrtl::core::NetlistDatabase;
+use eggrtl::hierarchy::Hierarchy;
+use eggrtl::saturate::{RuleSet, RunConfig};
+use serde_json::{json, Value};
+use std::{cell::RefCell, rc::Rc};
+
+
+#[test]
+fn import_and_saturation_send_json_progress_events() {
+    let events: Rc<RefCell<Vec<Value>>> = Rc::default();
+    let sink = events.clone();
+    let mut db = NetlistDatabase::default();
+    db.set_progress(Box::new(move |event| sink.borrow_mut().push(serde_json::to_value(event).unwrap())));
+    db.build_from_json(&fixture("mac.json"), "top", &["clk"], Hierarchy::Flatten).unwrap();
+
+    let import = events.borrow().last().cloned().expect("No import events");
+    assert_eq!(import["event"], "import");
+    assert_eq!((&import["cells_done"], &import["cells_total"], &import["percent"]), (&json!(7), &json!(7), &json!(100.0)));
+    assert!(import["cells_per_sec"].as_f64().unwrap() > 0.0, "{}", import);
+
+    let report = db.saturate(&RunConfig { rule_sets: vec![RuleSet::Basic], iter_limit: 3, ..RunConfig::default() });
+    let events = events.borrow();
+    let iterations: Vec<&Value> = events.iter().filter(|e| e["event"] == "iteration").collect();
+    assert_eq!(iterations.len(), report.iterations);
+    assert_eq!(iterations[0]["iteration"], 1);
+    assert!(iterations[0]["rows_added"].as_u64().unwrap() > 0, "{}", iterations[0]);
+
+    let last = events.last().unwrap();
+    assert_eq!(last["event"], "saturated");
+    assert_eq!(last["stop_reason"], format!("{:?}", report.stop_reason));
+    assert_eq!(last["rows"], report.nodes);
+}
@@ -0,0 +1,113 @@
+mod common;
+
+use common::{assert_one_driver_per_net, cells, import_json};
+use core_relations::{PlanStrategy, Value};
+use numeric_id::NumericId;
+use serde_json::json;
+
+
+/// Module `top` clocked by `clk`, with an enable `en`, a reset `rst`, 4-bit data `d` and the given cells.
+fn with_cells(cells: serde_json::Value) -> serde_json::Value {
+    json!({
+        "modules": {
+            "top": {
+                "ports": {
+                    "clk": { "direction": "input", "bits": [2] },
+                    "en": { "direction": "input", "bits": [3] },
+                    "rst": { "direction": "input", "bits": [4] },
+                    "d": { "direction": "input", "bits": [5, 6, 7, 8] },
+                    "q": { "direction": "output", "bits": [9, 10, 11, 12] },
+                    "r": { "direction": "output", "bits": [13, 14, 15, 16] }
+                },
+                "cells": cells
+            }
+        }
+    })
+}
+
+fn dffe() -> serde_json::Value {
+    json!({
+        "type": "$dffe",
+        "parameters": { "CLK_POLARITY": 1, "EN_POLARITY": 1, "WIDTH": 4 },
+        "connections": { "CLK": [2], "EN": [3], "D": [5, 6, 7, 8], "Q": [9, 10, 11, 12] }
+    })
+}
+
+fn sdff() -> serde_json::Value {
+    json!({
+        "type": "$sdff",
+        "parameters": { "CLK_POLARITY": 1, "SRST_POLARITY": 0, "SRST_VALUE": "0101", "WIDTH": 4 },
+        "connections": { "CLK": [2], "SRST": [4], "D": [5, 6, 7, 8], "Q": [13, 14, 15, 16] }
+    })
+}
+
+fn exported_cell<'a>(json: &'a serde_json::Value, ty: &str) -> &'a serde_json::Value {
+    json["modules"]["top"]["cells"].as_object().unwrap()
+        .values()
+        .find(|c| c["type"] == ty)
+        .unwrap_or_else(|| panic!("No {} cell in the export", ty))
+}
+
+#[test]
+fn flip_flops_keep_their_ports_polarities_and_reset_values() {
+    let db = import_json(with_cells(json!({ "dffe": dffe(), "sdff": sdff() })), "top");
+    let json = db.export_json("top");
+
+    let dffe = exported_cell(&json, "$dffe");
+    assert_eq!(dffe["parameters"]["EN_POLARITY"], json!(format!("{:032b}", 1)));
+    assert_eq!(dffe["connections"]["EN"], json!([3]));
+    let sdff = exported_cell(&json, "$sdff");
+    assert_eq!(sdff["parameters"]["SRST_POLARITY"], json!(format!("{:032b}", 0)));
+    assert_eq!(sdff["parameters"]["SRST_VALUE"], json!("0101"));
+    assert_eq!(sdff["connections"]["SRST"], json!([4]));
+
+    let reimported = import_json(json.clone(), "top");
+    assert_eq!(cells(&reimported.export_json("top"), "top"), cells(&json, "top"));
+}
+
+#[test]
+fn enable_flop_is_a_flop_behind_a_mux() {
+    let mut db = import_json(with_cells(json!({ "dffe": dffe() })), "top");
+    db.rewrite_register_all(PlanStrategy::PureSize, Value::new(0)..Value::new(1));
+    db.merge_all();
+    let json = db.export_json("top");
+
+    let mux = exported_cell(&json, "$mux");
+    assert_eq!(mux["connections"]["A"], json!([9, 10, 11, 12]));    // disabled, q keeps its value
+    assert_eq!(mux["connections"]["B"], json!([5, 6, 7, 8]));
+    assert_eq!(mux["connections"]["S"], json!([3]));
+    assert_eq!(exported_cell(&json, "$dff")["connections"]["D"], mux["connections"]["Y"]);
+    assert_one_driver_per_net(&json, "top");
+}
+
+#[test]
+fn export_verilog_guards_enable_flops() {
+    let db = import_json(with_cells(json!({ "dffe": dffe() })), "top");
+    let verilog = db.export_verilog("top").unwrap_or_else(|err| panic!("{}", err));
+    let always = verilog.lines().map(str::trim).find(|l| l.starts_with("always @(posedge _2_) ")).expect("No always block");
+    assert!(always.contains(" if (_3_) "), "{}", always);
+    assert!(always.ends_with(" <= {_8_, _7_, _6_, _5_};"), "{}", always);
+}
+
+#[test]
+fn registers_keep_their_own_clock_domain() {
+    let dff = |clk: i64, q: &[i64]| json!({
+        "type": "$dff",
+        "parameters": { "CLK_POLARITY": 1, "WIDTH": 4 },
+        "connections": { "CLK": [clk], "D": [5, 6, 7, 8], "Q": q }
+    });
+    let mut db = import_json(with_cells(json!({
+        "fast": dff(2, &[9, 10, 11, 12]),
+        "slow": dff(4, &[13, 14, 15, 16])
+    })), "top");
+    db.rewrite_register_all(PlanStrategy::PureSize, Value::new(0)..Value::new(1));
+    db.merge_all();
+    let json = db.export_json("top");
+
+    let mut clocks: Vec<_> = json["modules"]["top"]["cells"].as_object().unwrap()
+        .values()
+        .map(|c| (c["connections"]["Q"].clone(), c["connections"]["CLK"].clone()))
+        .collect();
+    clocks.sort_by_key(|(q, _)| q.to_string());
+    assert_eq!(clocks, vec![(json!([13, 14, 15, 16]), json!([4])), (json!([9, 10, 11, 12]), json!([2]))]);
+}
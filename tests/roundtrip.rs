@@ -0,0 +1,44 @@
+mod common;
+
+use common::{assert_one_driver_per_net, assert_same_tables, cells, fixture, import_file, import_json};
+use core_relations::{PlanStrategy, Value};
+use eggrtl::hierarchy::Hierarchy;
+use numeric_id::NumericId;
+
+
+#[test]
+fn export_json_reimports_to_the_same_tables() {
+    let db = import_file(&fixture("mac.json"), "top", Hierarchy::Flatten);
+    let stats = db.stats();
+    assert_eq!((stats.ay_cells, stats.aby_cells, stats.absy_cells, stats.dffs, stats.opaque_cells), (1, 3, 1, 1, 1));
+
+    let again = import_json(db.export_json("top"), "top");
+    assert_same_tables(&db, &again, "top");
+}
+
+#[test]
+fn export_json_keeps_constants_and_port_bits() {
+    let db = import_file(&fixture("mac.json"), "top", Hierarchy::Flatten);
+    let json = db.export_json("top");
+    let ports = &json["modules"]["top"]["ports"];
+    assert_eq!(ports["low"]["bits"], serde_json::json!([29, 30, "0", "0"]));
+    assert_eq!(ports["clk"]["direction"], "input");
+
+    let and = cells(&json, "top").into_iter()
+        .map(|c| serde_json::from_str::<serde_json::Value>(&c).unwrap())
+        .find(|c| c["type"] == "$and")
+        .expect("The $and cell is not exported");
+    assert_eq!(and["connections"]["B"], serde_json::json!(["1", "1", "0", "0"]));
+}
+
+#[test]
+fn export_json_drives_every_net_once_after_a_rewrite() {
+    let mut db = import_file(&fixture("mac.json"), "top", Hierarchy::Flatten);
+    let before = db.export_json("top");
+    db.rewrite_basic_all(PlanStrategy::PureSize, Value::new(0)..Value::new(1));
+    db.merge_all();
+
+    let after = db.export_json("top");
+    assert!(cells(&after, "top").len() > cells(&before, "top").len());
+    assert_one_driver_per_net(&after, "top");
+}
@@ -0,0 +1,15 @@
+mod common;
+
+use common::{fixture, import_file};
+use eggrtl::hierarchy::Hierarchy;
+use eggrtl::saturate::{RuleSet, RunConfig, StopReason};
+
+
+#[test]
+fn commuted_rows_found_again_reach_a_fixpoint() {
+    // the second iteration commutes the commuted adder back, a row that differs only in its timestamp
+    let mut db = import_file(&fixture("mac.json"), "top", Hierarchy::Flatten);
+    let report = db.saturate(&RunConfig { rule_sets: vec![RuleSet::Basic], iter_limit: 10, ..RunConfig::default() });
+    assert_eq!(report.stop_reason, StopReason::Saturated);
+    assert!(report.iterations < 10, "{:?}", report);
+}
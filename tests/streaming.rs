@@ -0,0 +1,27 @@
+mod common;
+
+use common::{assert_same_tables, fixture, import_file};
+use eggrtl::core::NetlistDatabase;
+use eggrtl::hierarchy::Hierarchy;
+
+
+fn stream_file(path: &str, top: &str, hierarchy: Hierarchy) -> NetlistDatabase {
+    let mut db = NetlistDatabase::default();
+    db.build_from_json(path, top, &[], hierarchy).unwrap_or_else(|err| panic!("{}", err));
+    db
+}
+
+#[test]
+fn streamed_import_matches_parsed_import() {
+    let path = fixture("mac.json");
+    assert_same_tables(&stream_file(&path, "top", Hierarchy::Flatten), &import_file(&path, "top", Hierarchy::Flatten), "top");
+}
+
+#[test]
+fn streamed_import_matches_parsed_import_with_instances() {
+    // wrap.json lists the instantiated module after the top one, and the top cells before the ports
+    let path = fixture("wrap.json");
+    for hierarchy in [Hierarchy::Flatten, Hierarchy::Keep] {
+        assert_same_tables(&stream_file(&path, "top", hierarchy), &import_file(&path, "top", hierarchy), "top");
+    }
+}
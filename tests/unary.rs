@@ -0,0 +1,76 @@
+mod common;
+
+use common::import_json;
+use core_relations::{PlanStrategy, Value};
+use eggrtl::core::NetlistDatabase;
+use numeric_id::NumericId;
+use serde_json::json;
+
+
+/// Module `top` with 4-bit inputs `a` and `b`, a 4-bit output `y` and the given cells.
+fn with_cells(cells: serde_json::Value) -> serde_json::Value {
+    json!({
+        "modules": {
+            "top": {
+                "ports": {
+                    "a": { "direction": "input", "bits": [2, 3, 4, 5] },
+                    "b": { "direction": "input", "bits": [6, 7, 8, 9] },
+                    "y": { "direction": "output", "bits": [10, 11, 12, 13] }
+                },
+                "cells": cells
+            }
+        }
+    })
+}
+
+fn ay_cell(ty: &str, a: &[i64], y: &[i64]) -> serde_json::Value {
+    json!({
+        "type": ty,
+        "parameters": { "A_SIGNED": 0, "A_WIDTH": a.len(), "Y_WIDTH": y.len() },
+        "connections": { "A": a, "Y": y }
+    })
+}
+
+fn rewrite_unary_once(db: &mut NetlistDatabase) -> serde_json::Value {
+    db.rewrite_unary_all(PlanStrategy::PureSize, Value::new(0)..Value::new(1));
+    db.merge_all();
+    db.export_json("top")
+}
+
+#[test]
+fn double_negation_is_the_operand() {
+    let mut db = import_json(with_cells(json!({
+        "neg1": ay_cell("$neg", &[2, 3, 4, 5], &[14, 15, 16, 17]),
+        "neg2": ay_cell("$neg", &[14, 15, 16, 17], &[10, 11, 12, 13])
+    })), "top");
+    let json = rewrite_unary_once(&mut db);
+    let ports = &json["modules"]["top"]["ports"];
+    assert_eq!(ports["y"]["bits"], ports["a"]["bits"]);
+}
+
+#[test]
+fn single_bit_reductions_are_their_operand() {
+    let mut db = import_json(with_cells(json!({
+        "or": ay_cell("$reduce_or", &[2], &[10])
+    })), "top");
+    let json = rewrite_unary_once(&mut db);
+    assert_eq!(json["modules"]["top"]["ports"]["y"]["bits"][0], json!(2));
+}
+
+#[test]
+fn subtraction_is_also_an_addition_of_the_negation() {
+    let mut db = import_json(with_cells(json!({
+        "sub": {
+            "type": "$sub",
+            "parameters": { "A_SIGNED": 0, "B_SIGNED": 0, "A_WIDTH": 4, "B_WIDTH": 4, "Y_WIDTH": 4 },
+            "connections": { "A": [2, 3, 4, 5], "B": [6, 7, 8, 9], "Y": [10, 11, 12, 13] }
+        }
+    })), "top");
+    let json = rewrite_unary_once(&mut db);
+    let types: Vec<serde_json::Value> = common::cells(&json, "top").iter()
+        .map(|c| serde_json::from_str::<serde_json::Value>(c).unwrap()["type"].clone())
+        .collect();
+    for ty in ["$sub", "$neg", "$add"] {
+        assert!(types.contains(&json!(ty)), "no {} in {:?}", ty, types);
+    }
+}
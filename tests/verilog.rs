@@ -0,0 +1,115 @@
+mod common;
+
+use common::{fixture, import_file, import_json};
+use eggrtl::error::VerilogError;
+use eggrtl::hierarchy::Hierarchy;
+use serde_json::json;
+
+
+#[test]
+fn export_verilog_writes_an_assign_per_cell_and_an_always_block_per_register() {
+    let db = import_file(&fixture("mac.json"), "top", Hierarchy::Flatten);
+    let verilog = db.export_verilog("top").unwrap_or_else(|err| panic!("{}", err));
+    let lines: Vec<&str> = verilog.lines().map(str::trim).collect();
+
+    for line in [
+        "assign {_20_, _19_, _18_, _17_} = {_6_, _5_, _4_, _3_} * {_10_, _9_, _8_, _7_};",
+        "assign {_24_, _23_, _22_, _21_} = $signed({_20_, _19_, _18_, _17_}) + $signed({_15_, _14_, _13_, _12_});",
+        "assign {_28_, _27_, _26_, _25_} = _11_ ? {_6_, _5_, _4_, _3_} : {_24_, _23_, _22_, _21_};",
+        "assign {_30_, _29_} = {_15_, _14_, _13_, _12_} & {1'b0, 1'b0, 1'b1, 1'b1};",
+        "assign low[2] = 1'b0;"
+    ] {
+        assert!(lines.contains(&line), "missing `{}` in\n{}", line, verilog);
+    }
+    let always: Vec<_> = lines.iter().filter(|l| l.starts_with("always @(posedge _2_) ")).collect();
+    assert_eq!(always.len(), 1, "{}", verilog);
+    assert!(always[0].ends_with(" <= {_28_, _27_, _26_, _25_};"), "{}", always[0]);
+}
+
+/// `y = a != b` and a `$pmux` driving `p`, both kept as opaque cells.
+fn ne_and_pmux(with_pmux: bool) -> serde_json::Value {
+    let mut cells = json!({
+        "ne": {
+            "type": "$ne",
+            "parameters": { "A_SIGNED": 0, "B_SIGNED": 0, "A_WIDTH": 2, "B_WIDTH": 2, "Y_WIDTH": 1 },
+            "connections": { "A": [2, 3], "B": [4, 5], "Y": [6] }
+        }
+    });
+    if with_pmux {
+        cells["pmux"] = json!({
+            "type": "$pmux",
+            "parameters": { "WIDTH": 1, "S_WIDTH": 2 },
+            "connections": { "A": [2], "B": [3, 4], "S": [5, 6], "Y": [7] }
+        });
+    }
+    json!({
+        "modules": {
+            "top": {
+                "ports": {
+                    "a": { "direction": "input", "bits": [2, 3] },
+                    "b": { "direction": "input", "bits": [4, 5] },
+                    "y": { "direction": "output", "bits": [6] },
+                    "p": { "direction": "output", "bits": [7] }
+                },
+                "cells": cells
+            }
+        }
+    })
+}
+
+#[test]
+fn export_verilog_instantiates_opaque_cells() {
+    let db = import_file(&fixture("mac.json"), "top", Hierarchy::Flatten);
+    let verilog = db.export_verilog("top").unwrap_or_else(|err| panic!("{}", err));
+    let rom = verilog.lines().map(str::trim).find(|l| l.starts_with("ROM16X4 ")).expect("No ROM16X4 instance");
+    assert!(rom.ends_with("(.ADDR({_6_, _5_, _4_, _3_}), .DO({_36_, _35_, _34_, _33_}));"), "{}", rom);
+}
+
+#[test]
+fn export_verilog_maps_ne_to_an_operator() {
+    let db = import_json(ne_and_pmux(false), "top");
+    let verilog = db.export_verilog("top").unwrap_or_else(|err| panic!("{}", err));
+    assert!(verilog.lines().any(|l| l.trim() == "assign _6_ = {_3_, _2_} != {_5_, _4_};"), "{}", verilog);
+}
+
+#[test]
+fn export_verilog_rejects_internal_cells_without_a_verilog_form() {
+    let db = import_json(ne_and_pmux(true), "top");
+    match db.export_verilog("top") {
+        Ok(verilog) => panic!("$pmux was written as\n{}", verilog),
+        Err(VerilogError::UnmappableTypes { types }) => assert_eq!(types, vec!["$pmux".to_string()])
+    }
+}
+
+/// `y = a >> b` as a `$shift` on four bits, with a signed or unsigned shift amount.
+fn shift(b_signed: bool) -> serde_json::Value {
+    json!({
+        "modules": {
+            "top": {
+                "ports": {
+                    "a": { "direction": "input", "bits": [2, 3, 4, 5] },
+                    "b": { "direction": "input", "bits": [6, 7] },
+                    "y": { "direction": "output", "bits": [8, 9, 10, 11] }
+                },
+                "cells": {
+                    "shift": {
+                        "type": "$shift",
+                        "parameters": { "A_SIGNED": 0, "B_SIGNED": b_signed as i64, "A_WIDTH": 4, "B_WIDTH": 2, "Y_WIDTH": 4 },
+                        "connections": { "A": [2, 3, 4, 5], "B": [6, 7], "Y": [8, 9, 10, 11] }
+                    }
+                }
+            }
+        }
+    })
+}
+
+#[test]
+fn export_verilog_shifts_left_by_negative_signed_amounts() {
+    let verilog = import_json(shift(true), "top").export_verilog("top").unwrap_or_else(|err| panic!("{}", err));
+    let line = "assign {_11_, _10_, _9_, _8_} = $signed({_7_, _6_}) < 0 ? {_5_, _4_, _3_, _2_} << -$signed({_7_, _6_}) : {_5_, _4_, _3_, _2_} >> {_7_, _6_};";
+    assert!(verilog.lines().any(|l| l.trim() == line), "{}", verilog);
+
+    let verilog = import_json(shift(false), "top").export_verilog("top").unwrap_or_else(|err| panic!("{}", err));
+    let line = "assign {_11_, _10_, _9_, _8_} = {_5_, _4_, _3_, _2_} >> {_7_, _6_};";
+    assert!(verilog.lines().any(|l| l.trim() == line), "{}", verilog);
+}